@@ -1 +1 @@
-cargo build --release --target arm-roborio-linux-gnueabi -Z build-std=std,panic_abort
\ No newline at end of file
+cargo build --release --features rio --target arm-roborio-linux-gnueabi -Z build-std=std,panic_abort
\ No newline at end of file
@@ -0,0 +1,466 @@
+//! Token based authentication for everything that changes the state of the host.
+//! Clients authenticate with the pre-shared api key as a bearer token,
+//! or log in with the password to get a session token that's also set as a cookie for the webpage.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    body::Body,
+    http::{
+        header::{AUTHORIZATION, COOKIE, SET_COOKIE},
+        HeaderMap, Method, Request,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use static_init::dynamic;
+use tokio::sync::Semaphore;
+
+use crate::types::routes;
+use crate::{ShiitakeError, ShiitakeResult};
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::warn!($($arg)*);
+    }
+}
+
+pub const SESSION_COOKIE: &str = "shiitake_session";
+pub const DEFAULT_SESSION_TTL: u64 = 12 * 60 * 60;
+///every argon2 check takes about 19 MiB, so only this many run at once
+const MAX_CONCURRENT_LOGINS: usize = 2;
+///failed logins in a row before the next attempt has to wait
+const FREE_LOGIN_FAILURES: u32 = 3;
+const MAX_LOGIN_DELAY: Duration = Duration::from_secs(60);
+///failures further apart than this don't count towards the delay
+const LOGIN_FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    ///pre-shared key accepted as a bearer token or as the login password
+    pub api_key: Option<String>,
+    ///argon2 PHC string, generate one with `shiitake --hash-password`
+    pub password_hash: Option<String>,
+    ///let GET requests through without authenticating
+    pub public_read: bool,
+    ///seconds a login session stays valid
+    pub session_ttl: u64,
+    ///serve routes that change the system without any credential configured, for trusted networks only
+    pub allow_unauthenticated: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            api_key: None,
+            password_hash: None,
+            public_read: true,
+            session_ttl: DEFAULT_SESSION_TTL,
+            allow_unauthenticated: false,
+        }
+    }
+}
+
+impl AuthConfig {
+    ///whether a credential has been configured, without one nothing can authenticate
+    pub fn required(&self) -> bool {
+        self.api_key.is_some() || self.password_hash.is_some()
+    }
+
+    fn check_password(&self, password: &str) -> bool {
+        if self.api_key.as_deref().is_some_and(|key| constant_time_eq(key.as_bytes(), password.as_bytes())) {
+            return true;
+        }
+        let Some(hash) = self.password_hash.as_deref() else {
+            return false;
+        };
+        match PasswordHash::new(hash) {
+            Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+            Err(_e) => {
+                warn!("Configured password_hash is not a valid PHC string: {}", _e);
+                false
+            }
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn random_bytes(count: usize) -> ShiitakeResult<Vec<u8>> {
+    let mut buf = vec![0u8; count];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn random_hex(count: usize) -> ShiitakeResult<String> {
+    Ok(random_bytes(count)?.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+pub fn hash_password(password: &str) -> ShiitakeResult<String> {
+    let salt = SaltString::encode_b64(&random_bytes(16)?)
+        .map_err(|e| ShiitakeError::BadRequest(e.to_string()))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| ShiitakeError::BadRequest(e.to_string()))
+}
+
+///logged in session tokens and when they expire
+#[derive(Debug, Default)]
+pub struct Sessions {
+    tokens: HashMap<String, Instant>,
+}
+
+impl Sessions {
+    pub fn create(&mut self, ttl: Duration) -> ShiitakeResult<String> {
+        let now = Instant::now();
+        self.tokens.retain(|_, expires| *expires > now);
+        let token = random_hex(32)?;
+        self.tokens.insert(token.clone(), now + ttl);
+        Ok(token)
+    }
+
+    pub fn is_valid(&self, token: &str) -> bool {
+        self.tokens.get(token).is_some_and(|expires| *expires > Instant::now())
+    }
+
+    pub fn remove(&mut self, token: &str) {
+        self.tokens.remove(token);
+    }
+}
+
+#[dynamic]
+static mut SESSIONS: Sessions = Sessions::default();
+
+///Failed logins in a row, every one past `FREE_LOGIN_FAILURES` doubles how long the next attempt waits.
+///Counted for the whole server, guessing from many addresses is no faster than from one.
+#[derive(Debug, Default)]
+struct LoginThrottle {
+    failures: u32,
+    last_failure: Option<Instant>,
+}
+
+impl LoginThrottle {
+    fn delay(&self) -> Duration {
+        match self.failures.checked_sub(FREE_LOGIN_FAILURES) {
+            Some(extra) => Duration::from_secs(1u64 << extra.min(6)).min(MAX_LOGIN_DELAY),
+            None => Duration::ZERO,
+        }
+    }
+
+    ///how long until another attempt is allowed
+    fn wait(&self, now: Instant) -> Option<Duration> {
+        let allowed = self.last_failure? + self.delay();
+        Some(allowed.saturating_duration_since(now)).filter(|wait| !wait.is_zero())
+    }
+
+    fn failed(&mut self, now: Instant) {
+        if self.last_failure.is_some_and(|last| now.duration_since(last) > LOGIN_FAILURE_WINDOW) {
+            self.failures = 0;
+        }
+        self.failures += 1;
+        self.last_failure = Some(now);
+    }
+
+    fn succeeded(&mut self) {
+        *self = LoginThrottle::default();
+    }
+}
+
+#[dynamic]
+static mut LOGIN_THROTTLE: LoginThrottle = LoginThrottle::default();
+#[dynamic]
+static LOGIN_PERMITS: Semaphore = Semaphore::new(MAX_CONCURRENT_LOGINS);
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+fn is_authenticated(config: &AuthConfig, headers: &HeaderMap) -> bool {
+    if let Some(token) = bearer_token(headers) {
+        let is_key = config
+            .api_key
+            .as_deref()
+            .is_some_and(|key| constant_time_eq(key.as_bytes(), token.as_bytes()));
+        if is_key || SESSIONS.read().is_valid(token) {
+            return true;
+        }
+    }
+    session_cookie(headers).is_some_and(|token| SESSIONS.read().is_valid(token))
+}
+
+///callers allowed to change the system, so also to have the server act on their behalf
+pub fn is_trusted(headers: &HeaderMap) -> bool {
+    let config = &crate::config::get().auth;
+    if !config.required() {
+        return config.allow_unauthenticated;
+    }
+    is_authenticated(config, headers)
+}
+
+fn is_auth_route(path: &str) -> bool {
+    [routes::AUTH_LOGIN, routes::AUTH_LOGOUT, routes::AUTH_STATUS].contains(&path)
+}
+
+///routes reachable without a token so the webpage can load and log in
+fn is_public(config: &AuthConfig, method: &Method, path: &str) -> bool {
+    if path == routes::ROOT || path == routes::RIO_PING || is_auth_route(path) {
+        return true;
+    }
+    config.public_read && (method == Method::GET || method == Method::HEAD)
+}
+
+fn authorize(read_only: bool, config: &AuthConfig, method: &Method, path: &str, headers: &HeaderMap) -> ShiitakeResult<()> {
+    let reads = method == Method::GET || method == Method::HEAD;
+    if read_only && !reads && !is_auth_route(path) {
+        return Err(ShiitakeError::Forbidden("The server is in read-only mode".to_string()));
+    }
+    if is_public(config, method, path) {
+        return Ok(());
+    }
+    if !config.required() {
+        if reads || config.allow_unauthenticated {
+            return Ok(());
+        }
+        return Err(ShiitakeError::Forbidden(
+            "No credentials are configured, set auth.api_key or auth.password_hash to change the system".to_string(),
+        ));
+    }
+    if is_authenticated(config, headers) {
+        return Ok(());
+    }
+    Err(ShiitakeError::Unauthorized)
+}
+
+pub async fn require_auth(request: Request<Body>, next: Next<Body>) -> ShiitakeResult<Response> {
+    let config = crate::config::get();
+    authorize(
+        config.server.read_only,
+        &config.auth,
+        request.method(),
+        request.uri().path(),
+        request.headers(),
+    )?;
+    Ok(next.run(request).await)
+}
+
+pub fn init_auth(app: Router) -> Router {
+    app.route(routes::AUTH_LOGIN, post(login))
+        .route(routes::AUTH_LOGOUT, post(logout))
+        .route(routes::AUTH_STATUS, get(status))
+}
+
+#[derive(Deserialize)]
+struct LoginRequest {
+    password: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginResponse {
+    token: String,
+    expires_in: u64,
+}
+
+async fn login(Json(request): Json<LoginRequest>) -> ShiitakeResult<Response> {
+    let config = &crate::config::get().auth;
+    if !config.required() {
+        return Err(ShiitakeError::BadRequest("No credentials are configured".to_string()));
+    }
+    if let Some(wait) = LOGIN_THROTTLE.read().wait(Instant::now()) {
+        return Err(ShiitakeError::TooManyRequests(format!(
+            "Too many failed logins, try again in {} seconds",
+            wait.as_secs().max(1)
+        )));
+    }
+    let permit = LOGIN_PERMITS
+        .try_acquire()
+        .map_err(|_| ShiitakeError::TooManyRequests("Other logins are still being checked".to_string()))?;
+    //argon2 takes long enough to stall every other request on the single threaded runtime,
+    //the permit goes with it so it's held until the check is done even if the client goes away
+    let password = request.password;
+    let valid = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        config.check_password(&password)
+    })
+    .await
+    .unwrap_or(false);
+    if !valid {
+        warn!("Failed login attempt");
+        LOGIN_THROTTLE.write().failed(Instant::now());
+        return Err(ShiitakeError::Unauthorized);
+    }
+    LOGIN_THROTTLE.write().succeeded();
+    let token = SESSIONS.write().create(Duration::from_secs(config.session_ttl))?;
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict",
+        SESSION_COOKIE, token, config.session_ttl
+    );
+    let body = LoginResponse {
+        token,
+        expires_in: config.session_ttl,
+    };
+    Ok(([(SET_COOKIE, cookie)], Json(body)).into_response())
+}
+
+async fn logout(headers: HeaderMap) -> impl IntoResponse {
+    let mut sessions = SESSIONS.write();
+    for token in [bearer_token(&headers), session_cookie(&headers)].into_iter().flatten() {
+        sessions.remove(token);
+    }
+    let cookie = format!("{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict", SESSION_COOKIE);
+    ([(SET_COOKIE, cookie)], "Logged out")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthStatus {
+    required: bool,
+    authenticated: bool,
+    public_read: bool,
+}
+
+async fn status(headers: HeaderMap) -> Json<AuthStatus> {
+    let config = &crate::config::get().auth;
+    Json(AuthStatus {
+        required: config.required(),
+        //what the middleware will let through, without credentials that's only with allow_unauthenticated
+        authenticated: is_trusted(&headers),
+        public_read: config.public_read,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_check_password() {
+        let config = AuthConfig {
+            api_key: Some("team6464".to_string()),
+            password_hash: Some(hash_password("mushroom").unwrap()),
+            ..Default::default()
+        };
+        assert!(config.check_password("mushroom"));
+        assert!(config.check_password("team6464"));
+        assert!(!config.check_password("toadstool"));
+        assert!(!AuthConfig::default().required());
+    }
+
+    #[test]
+    fn test_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer abc123"));
+        headers.insert(COOKIE, HeaderValue::from_static("theme=dark; shiitake_session=def456"));
+        assert_eq!(bearer_token(&headers), Some("abc123"));
+        assert_eq!(session_cookie(&headers), Some("def456"));
+
+        let config = AuthConfig {
+            api_key: Some("abc123".to_string()),
+            ..Default::default()
+        };
+        assert!(is_authenticated(&config, &headers));
+        assert!(is_public(&config, &Method::GET, routes::STATS));
+        assert!(!is_public(&config, &Method::POST, routes::REBOOT));
+        assert!(is_public(&config, &Method::POST, routes::AUTH_LOGIN));
+    }
+
+    #[test]
+    fn test_authorize() {
+        let headers = HeaderMap::new();
+        let open = AuthConfig::default();
+        assert!(authorize(false, &open, &Method::GET, routes::STATS, &headers).is_ok());
+        assert!(matches!(
+            authorize(false, &open, &Method::POST, routes::REBOOT, &headers),
+            Err(ShiitakeError::Forbidden(_))
+        ));
+        let private = AuthConfig {
+            public_read: false,
+            ..Default::default()
+        };
+        assert!(authorize(false, &private, &Method::GET, routes::STATS, &headers).is_ok());
+        let trusted = AuthConfig {
+            allow_unauthenticated: true,
+            ..Default::default()
+        };
+        assert!(authorize(false, &trusted, &Method::POST, routes::REBOOT, &headers).is_ok());
+        assert!(authorize(true, &trusted, &Method::POST, routes::REBOOT, &headers).is_err());
+
+        let keyed = AuthConfig {
+            api_key: Some("abc123".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            authorize(false, &keyed, &Method::POST, routes::REBOOT, &headers),
+            Err(ShiitakeError::Unauthorized)
+        ));
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer abc123"));
+        assert!(authorize(false, &keyed, &Method::POST, routes::REBOOT, &headers).is_ok());
+    }
+
+    #[test]
+    fn test_login_throttle() {
+        let mut throttle = LoginThrottle::default();
+        let start = Instant::now();
+        for _ in 0..FREE_LOGIN_FAILURES {
+            assert_eq!(throttle.wait(start), None);
+            throttle.failed(start);
+        }
+        assert_eq!(throttle.wait(start), Some(Duration::from_secs(1)));
+        throttle.failed(start);
+        assert_eq!(throttle.wait(start), Some(Duration::from_secs(2)));
+        assert_eq!(throttle.wait(start + Duration::from_secs(2)), None);
+        for _ in 0..10 {
+            throttle.failed(start);
+        }
+        assert_eq!(throttle.wait(start), Some(MAX_LOGIN_DELAY));
+
+        //a failure long after the last one starts counting again
+        throttle.failed(start + LOGIN_FAILURE_WINDOW * 2);
+        assert_eq!(throttle.failures, 1);
+        throttle.succeeded();
+        assert_eq!(throttle.wait(start), None);
+    }
+
+    #[test]
+    fn test_sessions() {
+        let mut sessions = Sessions::default();
+        let token = sessions.create(Duration::from_secs(60)).unwrap();
+        assert!(sessions.is_valid(&token));
+        sessions.remove(&token);
+        assert!(!sessions.is_valid(&token));
+        let expired = sessions.create(Duration::ZERO).unwrap();
+        assert!(!sessions.is_valid(&expired));
+    }
+}
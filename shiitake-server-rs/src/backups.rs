@@ -0,0 +1,330 @@
+//! Crash safe writes for the system's config files.
+//! Every write goes to a temporary file that's synced and renamed over the target, so a power loss leaves either the old or the new file.
+//! The file being replaced is copied into a rotating set of timestamped backups that can be listed, diffed and restored.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{
+    extract::Path as UrlPath,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::types::routes;
+use crate::{ShiitakeError, ShiitakeResult};
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::warn!($($arg)*);
+    }
+}
+
+pub const DEFAULT_BACKUP_DIR: &str = "/var/lib/shiitake/backups";
+pub const DEFAULT_BACKUP_KEEP: usize = 10;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    ///backups kept per file, 0 turns backups off
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: PathBuf::from(DEFAULT_BACKUP_DIR),
+            keep: DEFAULT_BACKUP_KEEP,
+        }
+    }
+}
+
+///replaces `path` with `contents` without ever leaving a partially written file behind
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let temp_path = dir.join(format!(".{}.shiitake-tmp", file_name.to_string_lossy()));
+
+    let result = (|| {
+        let mut temp = File::create(&temp_path)?;
+        temp.write_all(contents)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            temp.set_permissions(metadata.permissions())?;
+        }
+        temp.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+        //the rename itself only survives a power loss once the directory is synced
+        File::open(dir)?.sync_all()
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupEntry {
+    ///the millisecond timestamp the backup was taken at, unique across all files
+    pub id: u64,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+fn escape_path(path: &Path) -> String {
+    path.to_string_lossy().replace('%', "%25").replace('/', "%2F")
+}
+
+fn unescape_path(escaped: &str) -> PathBuf {
+    PathBuf::from(escaped.replace("%2F", "/").replace("%25", "%"))
+}
+
+static LAST_ID: AtomicU64 = AtomicU64::new(0);
+
+///a timestamp that's never handed out twice, even for writes in the same millisecond
+fn next_id() -> u64 {
+    let now = crate::history::now_millis();
+    let mut last = LAST_ID.load(Ordering::Relaxed);
+    loop {
+        let id = now.max(last + 1);
+        match LAST_ID.compare_exchange(last, id, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return id,
+            Err(actual) => last = actual,
+        }
+    }
+}
+
+///Backups live in one directory per file, named after the file's path, holding one file per backup named by its id.
+#[derive(Debug, Clone)]
+pub struct BackupStore {
+    pub dir: PathBuf,
+    pub keep: usize,
+}
+
+impl BackupStore {
+    pub fn from_config() -> Self {
+        let config = &crate::config::get().backups;
+        BackupStore {
+            dir: config.dir.clone(),
+            keep: config.keep,
+        }
+    }
+
+    fn file_dir(&self, path: &Path) -> PathBuf {
+        self.dir.join(escape_path(path))
+    }
+
+    ///writes `contents` to `path` atomically, backing up what was there first.
+    ///Writing the contents the file already has does nothing.
+    pub fn write(&self, path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        match std::fs::read(path) {
+            Ok(current) if current == contents => return Ok(()),
+            Ok(current) if self.keep > 0 => {
+                //a failed backup shouldn't stop the config from being saved
+                if let Err(_e) = self.backup(path, &current) {
+                    warn!("Failed to back up {}: {}", path.display(), _e);
+                }
+            }
+            _ => {}
+        }
+        write_atomic(path, contents)
+    }
+
+    fn backup(&self, path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        let dir = self.file_dir(path);
+        std::fs::create_dir_all(&dir)?;
+        write_atomic(&dir.join(next_id().to_string()), contents)?;
+
+        let mut ids = Self::ids_in(&dir)?;
+        ids.sort_unstable();
+        let excess = ids.len().saturating_sub(self.keep);
+        for id in &ids[..excess] {
+            std::fs::remove_file(dir.join(id.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn ids_in(dir: &Path) -> std::io::Result<Vec<u64>> {
+        Ok(std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect())
+    }
+
+    ///every backup, newest first
+    pub fn list(&self) -> std::io::Result<Vec<BackupEntry>> {
+        let Ok(dirs) = std::fs::read_dir(&self.dir) else {
+            return Ok(Vec::new());
+        };
+        let mut backups = Vec::new();
+        for dir in dirs.filter_map(|entry| entry.ok()) {
+            let Some(escaped) = dir.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            for id in Self::ids_in(&dir.path()).unwrap_or_default() {
+                backups.push(BackupEntry {
+                    id,
+                    path: unescape_path(&escaped),
+                    size: std::fs::metadata(dir.path().join(id.to_string()))?.len(),
+                });
+            }
+        }
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.id));
+        Ok(backups)
+    }
+
+    pub fn get(&self, id: u64) -> ShiitakeResult<BackupEntry> {
+        self.list()?
+            .into_iter()
+            .find(|backup| backup.id == id)
+            .ok_or_else(|| ShiitakeError::NotFound(format!("No backup {}", id)))
+    }
+
+    pub fn read(&self, backup: &BackupEntry) -> std::io::Result<Vec<u8>> {
+        std::fs::read(self.file_dir(&backup.path).join(backup.id.to_string()))
+    }
+
+    ///a diff from the backup to the file as it is now
+    pub fn diff(&self, id: u64) -> ShiitakeResult<String> {
+        let backup = self.get(id)?;
+        let old = String::from_utf8_lossy(&self.read(&backup)?).to_string();
+        let new = match std::fs::read(&backup.path) {
+            Ok(current) => String::from_utf8_lossy(&current).to_string(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut out = format!("--- {}@{}\n+++ {}\n", backup.path.display(), backup.id, backup.path.display());
+        for line in diff_lines(&old, &new) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    ///puts the backup's contents back, backing up the current file first so the restore can be undone
+    pub fn restore(&self, id: u64) -> ShiitakeResult<BackupEntry> {
+        let backup = self.get(id)?;
+        let contents = self.read(&backup)?;
+        self.write(&backup.path, &contents)?;
+        Ok(backup)
+    }
+}
+
+///every line of both texts prefixed with ` `, `-` or `+`, the files are small enough for a plain LCS table
+pub fn diff_lines(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!(" {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("-{}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}", new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+///atomically writes a system config file through the configured backup store
+pub fn write_config_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    BackupStore::from_config().write(path, contents)
+}
+
+pub fn init_backups(app: Router) -> Router {
+    app.route(routes::CONFIG_BACKUPS, get(list_backups))
+        .route(routes::CONFIG_BACKUP_DIFF, get(backup_diff))
+        .route(routes::CONFIG_BACKUP_RESTORE, post(restore_backup))
+}
+
+async fn list_backups() -> ShiitakeResult<Json<Vec<BackupEntry>>> {
+    Ok(Json(BackupStore::from_config().list()?))
+}
+
+async fn backup_diff(UrlPath(id): UrlPath<u64>) -> ShiitakeResult<String> {
+    BackupStore::from_config().diff(id)
+}
+
+async fn restore_backup(UrlPath(id): UrlPath<u64>) -> ShiitakeResult<Json<BackupEntry>> {
+    let backup = BackupStore::from_config().restore(id)?;
+    //the Rio keeps ni-rt.ini parsed in memory, reread it so the next save doesn't undo the restore
+    #[cfg(feature = "rio")]
+    crate::rio_interface::load_rio_data(&crate::config::get().rio.root)?;
+    Ok(Json(backup))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_write_and_rotate() {
+        let dir = TempDir::new("backups-rotate");
+        let store = BackupStore {
+            dir: dir.join("backups"),
+            keep: 2,
+        };
+        let target = dir.join("ni-rt.ini");
+        for i in 0..4 {
+            store.write(&target, format!("host_name=\"{}\"\n", i).as_bytes()).unwrap();
+        }
+        //rewriting the same contents doesn't take a backup
+        store.write(&target, b"host_name=\"3\"\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "host_name=\"3\"\n");
+        assert!(!dir.join(".ni-rt.ini.shiitake-tmp").exists());
+
+        let backups = store.list().unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].path, target);
+        assert!(backups[0].id > backups[1].id);
+        assert_eq!(store.read(&backups[0]).unwrap(), b"host_name=\"2\"\n");
+
+        assert_eq!(
+            store.diff(backups[0].id).unwrap(),
+            format!("--- {0}@{1}\n+++ {0}\n-host_name=\"2\"\n+host_name=\"3\"\n", target.display(), backups[0].id)
+        );
+
+        store.restore(backups[1].id).unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "host_name=\"1\"\n");
+        assert_eq!(store.list().unwrap().len(), 2);
+        assert!(store.get(1).is_err());
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let diff = diff_lines("[eth0]\ndhcpenabled=\"1\"\nMode=\"TCPIP\"\n", "[eth0]\ndhcpenabled=\"0\"\nMode=\"TCPIP\"\nGateway=\"10.64.64.1\"\n");
+        assert_eq!(
+            diff,
+            vec![" [eth0]", "-dhcpenabled=\"1\"", "+dhcpenabled=\"0\"", " Mode=\"TCPIP\"", "+Gateway=\"10.64.64.1\""]
+        );
+        assert_eq!(unescape_path(&escape_path(Path::new("/etc/100%/a"))), PathBuf::from("/etc/100%/a"));
+    }
+}
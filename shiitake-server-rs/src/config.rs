@@ -0,0 +1,477 @@
+//! The server's config file and command line flags, read once at startup.
+//! Flags override the file, and the result is validated before anything is bound or spawned.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::Deserialize;
+use thiserror::Error;
+use thread_priority::{ThreadPriority, ThreadPriorityValue};
+
+use crate::auth::AuthConfig;
+use crate::backups::BackupConfig;
+use crate::network::NetworkConfig;
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/shiitake/shiitake.toml";
+pub const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+///sampling faster than this costs more cpu than the measurements are worth
+pub const MIN_SAMPLE_INTERVAL_MS: u64 = 100;
+///every entry is a full `Stats` with all its interfaces, disks and sensors, a few KB each,
+///so this keeps the history around 10 MB at most on a 256 MB roboRIO
+pub const MAX_HISTORY_CAPACITY: usize = 3600;
+///the kernel keeps the first 15 bytes of a process name
+pub const MAX_PROCESS_NAME_LENGTH: usize = 15;
+
+pub const USAGE: &str = "\
+Usage: shiitake [OPTIONS]
+
+Options:
+  --config <PATH>            config file to read [default: /etc/shiitake/shiitake.toml]
+  --listen <ADDR>            address to serve on, can be repeated (e.g. 0.0.0.0:80, [::]:8080)
+  --thread-priority <PRIO>   min, max or 0-99
+  --log-level <LEVEL>        error, warn, info, debug or trace
+  --routes <GROUPS>          comma separated route groups to enable
+                             (stats, metrics, processes, process_control, time, reboot, network, backups,
+                              diagnostics, rio)
+  --rio-root <PATH>          directory the roboRIO's config files are read from [default: /]
+  --sample-interval <MS>     milliseconds between background stats samples
+  --history-capacity <N>     how many samples /stats/history keeps
+  --read-only                reject every request that would change the system
+  --hash-password            read a password from stdin and print its password_hash
+  --help                     print this message
+";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file: {0}")]
+    FileReadError(#[from] std::io::Error),
+    #[error("Invalid config file: {0}")]
+    ParseError(#[from] toml::de::Error),
+    #[error("Invalid argument: {0}\n\n{USAGE}")]
+    InvalidArgument(String),
+    #[error("Invalid config:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+///groups of routes that can be turned off, the webpage, summary, uptime and auth routes are always served
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteGroup {
+    Stats,
+    Metrics,
+    Processes,
+    ProcessControl,
+    Time,
+    Reboot,
+    Network,
+    Backups,
+    Diagnostics,
+    ///only served when built with the `rio` feature
+    Rio,
+}
+
+pub const ALL_ROUTE_GROUPS: &[RouteGroup] = &[
+    RouteGroup::Stats,
+    RouteGroup::Metrics,
+    RouteGroup::Processes,
+    RouteGroup::ProcessControl,
+    RouteGroup::Time,
+    RouteGroup::Reboot,
+    RouteGroup::Network,
+    RouteGroup::Backups,
+    RouteGroup::Diagnostics,
+    RouteGroup::Rio,
+];
+
+impl FromStr for RouteGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use serde::de::{value::StrDeserializer, IntoDeserializer};
+        let deserializer: StrDeserializer<serde::de::value::Error> = s.into_deserializer();
+        RouteGroup::deserialize(deserializer).map_err(|_| format!("Unknown route group {}", s))
+    }
+}
+
+///`"min"`, `"max"` or a cross platform priority from 0 to 99
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum PrioritySetting {
+    Named(String),
+    Value(u8),
+}
+
+impl PrioritySetting {
+    pub fn to_thread_priority(&self) -> Result<ThreadPriority, String> {
+        match self {
+            PrioritySetting::Named(name) if name.eq_ignore_ascii_case("min") => Ok(ThreadPriority::Min),
+            PrioritySetting::Named(name) if name.eq_ignore_ascii_case("max") => Ok(ThreadPriority::Max),
+            PrioritySetting::Named(name) => Err(format!("Unknown thread priority {}", name)),
+            PrioritySetting::Value(value) => ThreadPriorityValue::try_from(*value)
+                .map(ThreadPriority::Crossplatform)
+                .map_err(|e| format!("Thread priority {}: {}", value, e)),
+        }
+    }
+}
+
+impl FromStr for PrioritySetting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse::<u8>() {
+            Ok(value) => PrioritySetting::Value(value),
+            Err(_) => PrioritySetting::Named(s.to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: Vec<SocketAddr>,
+    pub thread_priority: PrioritySetting,
+    pub log_level: String,
+    pub routes: Vec<RouteGroup>,
+    ///serve GET requests only
+    pub read_only: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 80))],
+            thread_priority: PrioritySetting::Named("min".to_string()),
+            log_level: "info".to_string(),
+            routes: ALL_ROUTE_GROUPS.to_vec(),
+            read_only: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn enabled(&self, group: RouteGroup) -> bool {
+        self.routes.contains(&group)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingConfig {
+    ///milliseconds between the background sampler's measurements
+    pub interval_ms: u64,
+    ///samples kept for `/stats/history`, each one costs a few KB of memory
+    pub history_capacity: usize,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        SamplingConfig {
+            interval_ms: crate::history::DEFAULT_SAMPLE_INTERVAL.as_millis() as u64,
+            history_capacity: crate::history::DEFAULT_HISTORY_CAPACITY,
+        }
+    }
+}
+
+impl SamplingConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RioConfig {
+    ///prefix for every Rio file path, point it at a copy of the Rio's files to run off-target
+    pub root: PathBuf,
+}
+
+impl Default for RioConfig {
+    fn default() -> Self {
+        RioConfig {
+            root: PathBuf::from("/"),
+        }
+    }
+}
+
+impl RioConfig {
+    ///`relative` resolved against the root
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub sampling: SamplingConfig,
+    pub auth: AuthConfig,
+    pub rio: RioConfig,
+    pub backups: BackupConfig,
+    pub network: NetworkConfig,
+    ///process names the web UI can't signal or reschedule, on top of the built in ones
+    pub protected_processes: Vec<String>,
+}
+
+impl Config {
+    ///a missing file is the same as an empty one
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(file) => Self::parse(&file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(file: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(file)?)
+    }
+
+    pub fn apply_args(&mut self, args: &CliArgs) {
+        if !args.listen.is_empty() {
+            self.server.listen = args.listen.clone();
+        }
+        if let Some(priority) = &args.thread_priority {
+            self.server.thread_priority = priority.clone();
+        }
+        if let Some(level) = &args.log_level {
+            self.server.log_level = level.clone();
+        }
+        if let Some(routes) = &args.routes {
+            self.server.routes = routes.clone();
+        }
+        if args.read_only {
+            self.server.read_only = true;
+        }
+        if let Some(interval) = args.sample_interval {
+            self.sampling.interval_ms = interval;
+        }
+        if let Some(capacity) = args.history_capacity {
+            self.sampling.history_capacity = capacity;
+        }
+        if let Some(root) = &args.rio_root {
+            self.rio.root = root.clone();
+        }
+    }
+
+    ///checks everything serde can't, reporting every problem at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.server.listen.is_empty() {
+            errors.push("server.listen needs at least one address".to_string());
+        }
+        for (i, addr) in self.server.listen.iter().enumerate() {
+            if self.server.listen[..i].contains(addr) {
+                errors.push(format!("server.listen has {} more than once", addr));
+            }
+        }
+        if let Err(e) = self.server.thread_priority.to_thread_priority() {
+            errors.push(format!("server.thread_priority: {}", e));
+        }
+        if !LOG_LEVELS.contains(&self.server.log_level.to_lowercase().as_str()) {
+            errors.push(format!(
+                "server.log_level must be one of {}, got {}",
+                LOG_LEVELS.join(", "),
+                self.server.log_level
+            ));
+        }
+        if self.sampling.interval_ms < MIN_SAMPLE_INTERVAL_MS {
+            errors.push(format!("sampling.interval_ms must be at least {}", MIN_SAMPLE_INTERVAL_MS));
+        }
+        if !(1..=MAX_HISTORY_CAPACITY).contains(&self.sampling.history_capacity) {
+            errors.push(format!("sampling.history_capacity must be between 1 and {}", MAX_HISTORY_CAPACITY));
+        }
+        if self.auth.api_key.as_deref().is_some_and(str::is_empty) {
+            errors.push("auth.api_key can't be empty".to_string());
+        }
+        if let Some(hash) = &self.auth.password_hash {
+            if let Err(e) = argon2::password_hash::PasswordHash::new(hash) {
+                errors.push(format!("auth.password_hash isn't a valid PHC string: {}", e));
+            }
+        }
+        if self.auth.session_ttl == 0 {
+            errors.push("auth.session_ttl must be more than 0".to_string());
+        }
+        if !self.rio.root.is_dir() {
+            errors.push(format!("rio.root {} isn't a directory", self.rio.root.display()));
+        }
+        if !self.network.root.is_dir() {
+            errors.push(format!("network.root {} isn't a directory", self.network.root.display()));
+        }
+        for name in &self.protected_processes {
+            if name.is_empty() || name.len() > MAX_PROCESS_NAME_LENGTH {
+                errors.push(format!(
+                    "protected_processes: {:?} must be 1-{} characters, the kernel truncates longer names",
+                    name, MAX_PROCESS_NAME_LENGTH
+                ));
+            }
+        }
+        if !self.backups.dir.is_absolute() {
+            errors.push(format!("backups.dir {} must be an absolute path", self.backups.dir.display()));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CliArgs {
+    pub config: Option<PathBuf>,
+    pub listen: Vec<SocketAddr>,
+    pub thread_priority: Option<PrioritySetting>,
+    pub log_level: Option<String>,
+    pub routes: Option<Vec<RouteGroup>>,
+    pub sample_interval: Option<u64>,
+    pub history_capacity: Option<usize>,
+    pub rio_root: Option<PathBuf>,
+    pub read_only: bool,
+    pub hash_password: bool,
+    pub help: bool,
+}
+
+impl CliArgs {
+    pub fn config_path(&self) -> &Path {
+        self.config.as_deref().unwrap_or(Path::new(DEFAULT_CONFIG_PATH))
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, ConfigError>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = value.ok_or_else(|| ConfigError::InvalidArgument(format!("{} needs a value", flag)))?;
+    value
+        .parse()
+        .map_err(|e| ConfigError::InvalidArgument(format!("{} {}: {}", flag, value, e)))
+}
+
+///parses the flags after the program name, both `--flag value` and `--flag=value` are accepted
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<CliArgs, ConfigError> {
+    let mut parsed = CliArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, mut value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || value.take().or_else(|| args.next());
+        match flag.as_str() {
+            "--config" => parsed.config = Some(parse_value(&flag, value())?),
+            "--listen" => parsed.listen.push(parse_value(&flag, value())?),
+            "--thread-priority" => parsed.thread_priority = Some(parse_value(&flag, value())?),
+            "--log-level" => parsed.log_level = Some(parse_value(&flag, value())?),
+            "--routes" => {
+                let groups: String = parse_value(&flag, value())?;
+                parsed.routes = Some(
+                    groups
+                        .split(',')
+                        .filter(|group| !group.is_empty())
+                        .map(RouteGroup::from_str)
+                        .collect::<Result<_, _>>()
+                        .map_err(ConfigError::InvalidArgument)?,
+                );
+            }
+            "--sample-interval" => parsed.sample_interval = Some(parse_value(&flag, value())?),
+            "--history-capacity" => parsed.history_capacity = Some(parse_value(&flag, value())?),
+            "--rio-root" => parsed.rio_root = Some(parse_value(&flag, value())?),
+            "--read-only" => parsed.read_only = true,
+            "--hash-password" => parsed.hash_password = true,
+            "-h" | "--help" => parsed.help = true,
+            _ => return Err(ConfigError::InvalidArgument(format!("Unknown flag {}", flag))),
+        }
+    }
+    Ok(parsed)
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+///the loaded config, the defaults if `set` hasn't been called yet
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+///has no effect after the first call or after `get`
+pub fn set(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<CliArgs, ConfigError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            "[server]\nlisten = [\"0.0.0.0:5800\", \"[::]:5800\"]\nthread_priority = 10\nroutes = [\"stats\", \"network\"]\n\n[auth]\napi_key = \"hunter2\"\npublic_read = false\n",
+        )
+        .unwrap();
+        assert_eq!(config.server.listen.len(), 2);
+        assert!(config.server.listen[1].is_ipv6());
+        assert_eq!(config.server.thread_priority, PrioritySetting::Value(10));
+        assert!(config.server.enabled(RouteGroup::Network));
+        assert!(!config.server.enabled(RouteGroup::Reboot));
+        assert_eq!(config.auth.api_key.as_deref(), Some("hunter2"));
+        assert!(!config.auth.public_read);
+        assert!(config.validate().is_ok());
+
+        assert!(Config::parse("[auth]\napi_kye = \"typo\"\n").is_err());
+        assert!(Config::parse("[server]\nroutes = [\"everything\"]\n").is_err());
+        assert!(Config::parse("").unwrap().validate().is_ok());
+
+        let config = Config::parse("protected_processes = [\"FRCNetComm\"]\n").unwrap();
+        assert_eq!(config.protected_processes, vec!["FRCNetComm"]);
+        assert!(config.validate().is_ok());
+        let config = Config::parse("protected_processes = [\"a_name_the_kernel_cuts_off\"]\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = Config::default();
+        config.server.listen.clear();
+        config.server.log_level = "loud".to_string();
+        config.server.thread_priority = PrioritySetting::Value(120);
+        config.sampling.interval_ms = 10;
+        let Err(ConfigError::Invalid(errors)) = config.validate() else {
+            panic!("config should be invalid");
+        };
+        assert_eq!(errors.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&[
+            "--listen", "127.0.0.1:8080", "--listen=[::1]:8080", "--routes", "stats,metrics", "--read-only",
+            "--sample-interval", "500", "--thread-priority", "max", "--rio-root", "fixtures/rio",
+        ])
+        .unwrap();
+        assert_eq!(parsed.listen.len(), 2);
+        assert_eq!(parsed.routes, Some(vec![RouteGroup::Stats, RouteGroup::Metrics]));
+        assert_eq!(parsed.config_path(), Path::new(DEFAULT_CONFIG_PATH));
+
+        let mut config = Config::default();
+        config.apply_args(&parsed);
+        assert!(config.server.read_only);
+        assert_eq!(config.sampling.interval(), Duration::from_millis(500));
+        assert_eq!(config.server.listen, parsed.listen);
+        assert_eq!(config.rio.path("etc/hostname"), Path::new("fixtures/rio/etc/hostname"));
+        assert!(config.validate().is_ok());
+
+        assert!(args(&["--listen", "localhost"]).is_err());
+        assert!(args(&["--routes", "stats,bogus"]).is_err());
+        assert!(args(&["--sample-interval"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+    }
+}
@@ -0,0 +1,699 @@
+//! Checking what the server can reach from where it sits, for when the driver station can't see the robot.
+//! Every diagnostic is capped in how many packets it sends and how long it runs, only one runs at a time
+//! and only so many may start each minute, so the endpoints can't be turned into a flood.
+
+use std::collections::VecDeque;
+use std::io::IoSliceMut;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, ToSocketAddrs};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::{routing::post, Json, Router};
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket::{
+    self, sockopt, AddressFamily, ControlMessageOwned, MsgFlags, SockFlag, SockProtocol, SockType, SockaddrIn,
+};
+use nix::sys::time::{TimeVal, TimeValLike};
+use serde::{Deserialize, Serialize};
+use static_init::dynamic;
+
+use crate::types::routes;
+use crate::{ShiitakeError, ShiitakeResult};
+
+pub const DEFAULT_COUNT: u32 = 4;
+pub const MAX_PING_COUNT: u32 = 20;
+pub const MAX_TCP_COUNT: u32 = 10;
+pub const DEFAULT_INTERVAL_MS: u64 = 1000;
+///what iputils' ping allows users without root
+pub const MIN_INTERVAL_MS: u64 = 200;
+pub const MAX_INTERVAL_MS: u64 = 5000;
+pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+pub const MIN_TIMEOUT_MS: u64 = 10;
+pub const MAX_TIMEOUT_MS: u64 = 5000;
+pub const DEFAULT_MAX_HOPS: u8 = 15;
+pub const MAX_HOPS: u8 = 30;
+///the longest any diagnostic is allowed to take
+pub const MAX_DURATION: Duration = Duration::from_secs(60);
+pub const MAX_RUNS_PER_MINUTE: usize = 20;
+///getaddrinfo retries every nameserver on its own, this bounds the response rather than the lookup
+const DNS_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_NAME_LENGTH: usize = 253;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_UNREACHABLE: u8 = 3;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+///the same 64 byte packets as ping
+const PAYLOAD_SIZE: usize = 56;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static NEXT_ICMP_ID: AtomicU16 = AtomicU16::new(0);
+#[dynamic]
+static mut RECENT_RUNS: VecDeque<Instant> = VecDeque::new();
+
+///held for as long as a diagnostic runs
+struct RunGuard;
+
+impl RunGuard {
+    fn acquire() -> ShiitakeResult<Self> {
+        if RUNNING.swap(true, Ordering::AcqRel) {
+            return Err(ShiitakeError::TooManyRequests("Another diagnostic is still running".to_string()));
+        }
+        let now = Instant::now();
+        let mut runs = RECENT_RUNS.write();
+        while runs.front().is_some_and(|run| now.duration_since(*run) >= Duration::from_secs(60)) {
+            runs.pop_front();
+        }
+        if runs.len() >= MAX_RUNS_PER_MINUTE {
+            RUNNING.store(false, Ordering::Release);
+            return Err(ShiitakeError::TooManyRequests(format!(
+                "Only {} diagnostics can run per minute",
+                MAX_RUNS_PER_MINUTE
+            )));
+        }
+        runs.push_back(now);
+        Ok(RunGuard)
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+///runs a diagnostic off the async runtime, the slot stays taken until it finishes even if the client goes away
+async fn run_blocking<T: Send + 'static>(
+    diagnostic: impl FnOnce() -> ShiitakeResult<T> + Send + 'static,
+) -> ShiitakeResult<T> {
+    let guard = RunGuard::acquire()?;
+    tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        diagnostic()
+    })
+    .await
+    .unwrap_or_else(|_| Err(ShiitakeError::CommandFailed("The diagnostic panicked".to_string())))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn check_range(name: &str, value: u64, min: u64, max: u64) -> ShiitakeResult<()> {
+    if !(min..=max).contains(&value) {
+        return Err(ShiitakeError::BadRequest(format!("{} must be between {} and {}", name, min, max)));
+    }
+    Ok(())
+}
+
+fn check_duration(runs: u64, each: Duration) -> ShiitakeResult<()> {
+    if each * runs as u32 > MAX_DURATION {
+        return Err(ShiitakeError::BadRequest(format!(
+            "The diagnostic could take {} seconds, the limit is {}",
+            (each * runs as u32).as_secs(),
+            MAX_DURATION.as_secs()
+        )));
+    }
+    Ok(())
+}
+
+///an address or a name to look up, refusing anything that reaches more than one host
+fn resolve(host: &str, ipv4_only: bool) -> ShiitakeResult<IpAddr> {
+    let address = match host.parse::<IpAddr>() {
+        Ok(address) => address,
+        Err(_) => (host, 0)
+            .to_socket_addrs()
+            .map_err(|e| ShiitakeError::BadRequest(format!("Couldn't resolve {}: {}", host, e)))?
+            .map(|address| address.ip())
+            .find(|address| address.is_ipv4() || !ipv4_only)
+            .ok_or_else(|| ShiitakeError::BadRequest(format!("{} has no usable address", host)))?,
+    };
+    let broadcast = matches!(address, IpAddr::V4(v4) if v4.is_broadcast());
+    if address.is_unspecified() || address.is_multicast() || broadcast {
+        return Err(ShiitakeError::BadRequest(format!("{} isn't a single host", address)));
+    }
+    if ipv4_only && address.is_ipv6() {
+        return Err(ShiitakeError::BadRequest("Only IPv4 hosts are supported".to_string()));
+    }
+    Ok(address)
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyStats {
+    pub sent: u32,
+    pub received: u32,
+    pub loss_percent: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    ///how much the round trip times jitter
+    pub stddev_ms: Option<f64>,
+}
+
+impl LatencyStats {
+    ///`samples` holds the round trip time of every attempt, `None` for the ones that got no answer
+    pub fn from_samples(samples: &[Option<f64>]) -> Self {
+        let times = samples.iter().flatten().copied().collect::<Vec<_>>();
+        let sent = samples.len() as u32;
+        let received = times.len() as u32;
+        let avg = (!times.is_empty()).then(|| times.iter().sum::<f64>() / times.len() as f64);
+        LatencyStats {
+            sent,
+            received,
+            loss_percent: if sent == 0 { 0.0 } else { 100.0 * (sent - received) as f64 / sent as f64 },
+            min_ms: times.iter().copied().reduce(f64::min),
+            avg_ms: avg,
+            max_ms: times.iter().copied().reduce(f64::max),
+            stddev_ms: avg.map(|avg| {
+                (times.iter().map(|time| (time - avg).powi(2)).sum::<f64>() / times.len() as f64).sqrt()
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IcmpKind {
+    EchoReply,
+    TimeExceeded,
+    Unreachable,
+}
+
+#[derive(Debug, PartialEq)]
+struct IcmpReply {
+    kind: IcmpKind,
+    seq: u16,
+}
+
+fn checksum(bytes: &[u8]) -> u16 {
+    let mut sum = bytes
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(id: u16, seq: u16) -> Vec<u8> {
+    let mut packet = vec![ICMP_ECHO_REQUEST, 0, 0, 0];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend((0..PAYLOAD_SIZE).map(|i| i as u8));
+    let sum = checksum(&packet);
+    packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    packet
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]))
+}
+
+fn strip_ip_header(packet: &[u8]) -> Option<&[u8]> {
+    let header_length = usize::from(packet.first()? & 0x0f) * 4;
+    packet.get(header_length..)
+}
+
+///what an ICMP packet says about one of our echo requests,
+///raw sockets see every ICMP packet with its IP header, ping sockets only the replies to their own requests
+fn parse_reply(packet: &[u8], raw: bool, id: u16) -> Option<IcmpReply> {
+    let icmp = if raw { strip_ip_header(packet)? } else { packet };
+    let kind = match *icmp.first()? {
+        ICMP_ECHO_REPLY => {
+            if raw && read_u16(icmp, 4)? != id {
+                return None;
+            }
+            return Some(IcmpReply {
+                kind: IcmpKind::EchoReply,
+                seq: read_u16(icmp, 6)?,
+            });
+        }
+        ICMP_TIME_EXCEEDED => IcmpKind::TimeExceeded,
+        ICMP_UNREACHABLE => IcmpKind::Unreachable,
+        _ => return None,
+    };
+    //errors quote the start of the packet that caused them
+    let original = strip_ip_header(icmp.get(8..)?)?;
+    if *original.first()? != ICMP_ECHO_REQUEST || (raw && read_u16(original, 4)? != id) {
+        return None;
+    }
+    Some(IcmpReply {
+        kind,
+        seq: read_u16(original, 6)?,
+    })
+}
+
+struct IcmpSocket {
+    fd: OwnedFd,
+    raw: bool,
+    id: u16,
+}
+
+impl IcmpSocket {
+    fn open() -> ShiitakeResult<Self> {
+        //ping sockets need the server's group in net.ipv4.ping_group_range, raw ones need root
+        let (fd, raw) = match socket::socket(
+            AddressFamily::Inet,
+            SockType::Datagram,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::Icmp,
+        ) {
+            Ok(fd) => (fd, false),
+            Err(Errno::EACCES | Errno::EPERM) => (
+                socket::socket(AddressFamily::Inet, SockType::Raw, SockFlag::SOCK_CLOEXEC, SockProtocol::Icmp)?,
+                true,
+            ),
+            Err(e) => return Err(e.into()),
+        };
+        if !raw {
+            //ping sockets only hear about time exceeded and unreachable through the error queue
+            socket::setsockopt(&fd, sockopt::Ipv4RecvErr, &true)?;
+        }
+        Ok(IcmpSocket {
+            fd,
+            raw,
+            //the kernel picks the id of ping sockets, raw ones share every ICMP packet with other pingers
+            id: (std::process::id() as u16).wrapping_add(NEXT_ICMP_ID.fetch_add(1, Ordering::Relaxed)),
+        })
+    }
+
+    fn set_ttl(&self, ttl: u8) -> ShiitakeResult<()> {
+        Ok(socket::setsockopt(&self.fd, sockopt::Ipv4Ttl, &i32::from(ttl))?)
+    }
+
+    fn send(&self, to: Ipv4Addr, seq: u16) -> nix::Result<usize> {
+        let to = SockaddrIn::from(SocketAddrV4::new(to, 0));
+        socket::sendto(self.fd.as_raw_fd(), &echo_request(self.id, seq), &to, MsgFlags::empty())
+    }
+
+    ///waits for an answer to the request numbered `seq`, `None` when the deadline passes first
+    fn receive(&self, seq: u16, deadline: Instant) -> ShiitakeResult<Option<(IcmpKind, Ipv4Addr)>> {
+        let mut buffer = [0u8; 1500];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            //a zero timeout would block forever
+            let timeout = TimeVal::microseconds((remaining.as_micros() as i64).max(1));
+            socket::setsockopt(&self.fd, sockopt::ReceiveTimeout, &timeout)?;
+            let answer = match socket::recvfrom::<SockaddrIn>(self.fd.as_raw_fd(), &mut buffer) {
+                Ok((length, from)) => parse_reply(&buffer[..length], self.raw, self.id)
+                    .zip(from.map(|from| from.ip())),
+                Err(Errno::EAGAIN | Errno::EINTR) => continue,
+                //a queued ICMP error fails the read on ping sockets
+                Err(e) if !self.raw => Some(self.read_error_queue()?.ok_or(e)?),
+                Err(e) => return Err(e.into()),
+            };
+            if let Some((reply, from)) = answer {
+                if reply.seq == seq {
+                    return Ok(Some((reply.kind, from)));
+                }
+            }
+        }
+    }
+
+    fn read_error_queue(&self) -> ShiitakeResult<Option<(IcmpReply, Ipv4Addr)>> {
+        let mut buffer = [0u8; 576];
+        let mut iov = [IoSliceMut::new(&mut buffer)];
+        let mut control = nix::cmsg_space!(libc::sock_extended_err, libc::sockaddr_in);
+        let flags = MsgFlags::MSG_ERRQUEUE | MsgFlags::MSG_DONTWAIT;
+        let message = match socket::recvmsg::<SockaddrIn>(self.fd.as_raw_fd(), &mut iov, Some(&mut control), flags) {
+            Ok(message) => message,
+            Err(Errno::EAGAIN) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let length = message.bytes;
+        let error = message.cmsgs()?.find_map(|control| match control {
+            ControlMessageOwned::Ipv4RecvErr(error, Some(offender))
+                if error.ee_origin == libc::SO_EE_ORIGIN_ICMP =>
+            {
+                Some((error.ee_type, Ipv4Addr::from(u32::from_be(offender.sin_addr.s_addr))))
+            }
+            _ => None,
+        });
+        //the queued packet is the request the error is about
+        let Some(seq) = read_u16(&buffer[..length], 6) else {
+            return Ok(None);
+        };
+        Ok(error.and_then(|(icmp_type, from)| {
+            let kind = match icmp_type {
+                ICMP_TIME_EXCEEDED => IcmpKind::TimeExceeded,
+                ICMP_UNREACHABLE => IcmpKind::Unreachable,
+                _ => return None,
+            };
+            Some((IcmpReply { kind, seq }, from))
+        }))
+    }
+}
+
+pub fn init_diagnostics(app: Router) -> Router {
+    app.route(routes::DIAGNOSTICS_PING, post(ping))
+        .route(routes::DIAGNOSTICS_TRACEROUTE, post(traceroute))
+        .route(routes::DIAGNOSTICS_TCP, post(tcp_probe))
+        .route(routes::DIAGNOSTICS_DNS, post(dns_lookup))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct PingRequest {
+    host: String,
+    count: Option<u32>,
+    ///milliseconds between requests
+    interval_ms: Option<u64>,
+    ///milliseconds to wait for each reply
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PingResult {
+    host: String,
+    address: IpAddr,
+    ///round trip time of every request in milliseconds, `null` for the lost ones
+    replies: Vec<Option<f64>>,
+    #[serde(flatten)]
+    stats: LatencyStats,
+    ///the last reason a request failed, if one was given
+    error: Option<String>,
+}
+
+async fn ping(Json(request): Json<PingRequest>) -> ShiitakeResult<Json<PingResult>> {
+    let count = request.count.unwrap_or(DEFAULT_COUNT);
+    let interval = request.interval_ms.unwrap_or(DEFAULT_INTERVAL_MS);
+    let timeout = request.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+    check_range("count", count.into(), 1, MAX_PING_COUNT.into())?;
+    check_range("intervalMs", interval, MIN_INTERVAL_MS, MAX_INTERVAL_MS)?;
+    check_range("timeoutMs", timeout, MIN_TIMEOUT_MS, MAX_TIMEOUT_MS)?;
+    let (interval, timeout) = (Duration::from_millis(interval), Duration::from_millis(timeout));
+    check_duration(count.into(), interval.max(timeout))?;
+
+    let result = run_blocking(move || {
+        let IpAddr::V4(address) = resolve(&request.host, true)? else {
+            unreachable!("resolve only returns IPv4 addresses when asked to");
+        };
+        let socket = IcmpSocket::open()?;
+        let mut replies = Vec::new();
+        let mut error = None;
+        for seq in 0..count as u16 {
+            let sent = Instant::now();
+            let reply = match socket.send(address, seq) {
+                Ok(_) => socket.receive(seq, sent + timeout)?,
+                Err(e) => {
+                    error = Some(e.desc().to_string());
+                    None
+                }
+            };
+            replies.push(match reply {
+                Some((IcmpKind::EchoReply, _)) => Some(millis(sent.elapsed())),
+                Some((kind, from)) => {
+                    error = Some(format!("{:?} from {}", kind, from));
+                    None
+                }
+                None => None,
+            });
+            if u32::from(seq) + 1 < count {
+                std::thread::sleep((sent + interval).saturating_duration_since(Instant::now()));
+            }
+        }
+        Ok(PingResult {
+            host: request.host,
+            address: IpAddr::V4(address),
+            stats: LatencyStats::from_samples(&replies),
+            replies,
+            error,
+        })
+    })
+    .await?;
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct TracerouteRequest {
+    host: String,
+    max_hops: Option<u8>,
+    ///milliseconds to wait for each hop to answer
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Hop {
+    ttl: u8,
+    ///`null` when nothing answered in time
+    address: Option<IpAddr>,
+    rtt_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TracerouteResult {
+    host: String,
+    address: IpAddr,
+    hops: Vec<Hop>,
+    reached: bool,
+}
+
+async fn traceroute(Json(request): Json<TracerouteRequest>) -> ShiitakeResult<Json<TracerouteResult>> {
+    let max_hops = request.max_hops.unwrap_or(DEFAULT_MAX_HOPS);
+    let timeout = request.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+    check_range("maxHops", max_hops.into(), 1, MAX_HOPS.into())?;
+    check_range("timeoutMs", timeout, MIN_TIMEOUT_MS, MAX_TIMEOUT_MS)?;
+    let timeout = Duration::from_millis(timeout);
+    check_duration(max_hops.into(), timeout)?;
+
+    let result = run_blocking(move || {
+        let IpAddr::V4(address) = resolve(&request.host, true)? else {
+            unreachable!("resolve only returns IPv4 addresses when asked to");
+        };
+        let socket = IcmpSocket::open()?;
+        let mut hops = Vec::new();
+        let mut reached = false;
+        //one probe per hop, numbered by its ttl
+        for ttl in 1..=max_hops {
+            socket.set_ttl(ttl)?;
+            let sent = Instant::now();
+            let answer = match socket.send(address, ttl.into()) {
+                Ok(_) => socket.receive(ttl.into(), sent + timeout)?,
+                Err(_) => None,
+            };
+            hops.push(Hop {
+                ttl,
+                address: answer.map(|(_, from)| IpAddr::V4(from)),
+                rtt_ms: answer.map(|_| millis(sent.elapsed())),
+            });
+            match answer {
+                Some((IcmpKind::EchoReply, _)) => {
+                    reached = true;
+                    break;
+                }
+                Some((IcmpKind::Unreachable, _)) => break,
+                _ => {}
+            }
+        }
+        Ok(TracerouteResult {
+            host: request.host,
+            address: IpAddr::V4(address),
+            hops,
+            reached,
+        })
+    })
+    .await?;
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct TcpProbeRequest {
+    host: String,
+    port: u16,
+    count: Option<u32>,
+    ///milliseconds between connection attempts
+    interval_ms: Option<u64>,
+    ///milliseconds to wait for each connection
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TcpProbeResult {
+    host: String,
+    address: IpAddr,
+    port: u16,
+    ///time to connect for every attempt in milliseconds, `null` for the failed ones
+    connects: Vec<Option<f64>>,
+    #[serde(flatten)]
+    stats: LatencyStats,
+    ///why the last failed attempt failed, a refused connection still means the host is up
+    error: Option<String>,
+}
+
+async fn tcp_probe(Json(request): Json<TcpProbeRequest>) -> ShiitakeResult<Json<TcpProbeResult>> {
+    let count = request.count.unwrap_or(DEFAULT_COUNT);
+    let interval = request.interval_ms.unwrap_or(DEFAULT_INTERVAL_MS);
+    let timeout = request.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+    check_range("port", request.port.into(), 1, u16::MAX.into())?;
+    check_range("count", count.into(), 1, MAX_TCP_COUNT.into())?;
+    check_range("intervalMs", interval, MIN_INTERVAL_MS, MAX_INTERVAL_MS)?;
+    check_range("timeoutMs", timeout, MIN_TIMEOUT_MS, MAX_TIMEOUT_MS)?;
+    let (interval, timeout) = (Duration::from_millis(interval), Duration::from_millis(timeout));
+    check_duration(count.into(), interval.max(timeout))?;
+
+    let result = run_blocking(move || {
+        let address = resolve(&request.host, false)?;
+        let mut connects = Vec::new();
+        let mut error = None;
+        for attempt in 0..count {
+            let started = Instant::now();
+            match TcpStream::connect_timeout(&SocketAddr::new(address, request.port), timeout) {
+                Ok(_) => connects.push(Some(millis(started.elapsed()))),
+                Err(e) => {
+                    error = Some(e.to_string());
+                    connects.push(None);
+                }
+            }
+            if attempt + 1 < count {
+                std::thread::sleep((started + interval).saturating_duration_since(Instant::now()));
+            }
+        }
+        Ok(TcpProbeResult {
+            host: request.host,
+            address,
+            port: request.port,
+            stats: LatencyStats::from_samples(&connects),
+            connects,
+            error,
+        })
+    })
+    .await?;
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DnsRequest {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DnsResult {
+    name: String,
+    addresses: Vec<IpAddr>,
+    elapsed_ms: f64,
+    ///the servers the lookup went to
+    nameservers: Vec<IpAddr>,
+    error: Option<String>,
+}
+
+async fn dns_lookup(Json(request): Json<DnsRequest>) -> ShiitakeResult<Json<DnsResult>> {
+    let name = request.name.trim().trim_end_matches('.').to_string();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(ShiitakeError::BadRequest(format!(
+            "name must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    let nameservers = crate::network::parse_nameservers(
+        &std::fs::read_to_string(crate::network::resolv_conf_path()).unwrap_or_default(),
+    );
+    let started = Instant::now();
+    let lookup_name = name.clone();
+    let lookup = run_blocking(move || {
+        let mut addresses = Vec::new();
+        for address in (lookup_name.as_str(), 0).to_socket_addrs()? {
+            if !addresses.contains(&address.ip()) {
+                addresses.push(address.ip());
+            }
+        }
+        Ok(addresses)
+    });
+    let (addresses, error) = match tokio::time::timeout(DNS_TIMEOUT, lookup).await {
+        Ok(Ok(addresses)) => (addresses, None),
+        Ok(Err(ShiitakeError::FileReadError(e))) => (Vec::new(), Some(e.to_string())),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => (Vec::new(), Some(format!("No answer within {} seconds", DNS_TIMEOUT.as_secs()))),
+    };
+    Ok(Json(DnsResult {
+        name,
+        addresses,
+        elapsed_ms: millis(started.elapsed()),
+        nameservers,
+        error,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_echo_request() {
+        let packet = echo_request(0x1234, 7);
+        assert_eq!(packet.len(), 8 + PAYLOAD_SIZE);
+        assert_eq!(read_u16(&packet, 4), Some(0x1234));
+        assert_eq!(read_u16(&packet, 6), Some(7));
+        //a packet with a valid checksum sums to zero
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let ip_header = [0x45u8, 0, 0, 84, 0, 0, 0, 0, 64, 1, 0, 0, 10, 64, 64, 1, 10, 64, 64, 2];
+        let mut reply = echo_request(0x1234, 3);
+        reply[0] = ICMP_ECHO_REPLY;
+        let raw_reply = [&ip_header[..], &reply].concat();
+        let echo = IcmpReply { kind: IcmpKind::EchoReply, seq: 3 };
+        assert_eq!(parse_reply(&raw_reply, true, 0x1234), Some(echo));
+        assert_eq!(parse_reply(&raw_reply, true, 0x4321), None);
+        assert_eq!(parse_reply(&reply, false, 0), Some(IcmpReply { kind: IcmpKind::EchoReply, seq: 3 }));
+
+        //time exceeded quotes the IP header and first 8 bytes of the request
+        let request = echo_request(0x1234, 5);
+        let time_exceeded = [&ip_header[..], &[ICMP_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0], &ip_header, &request[..8]].concat();
+        assert_eq!(
+            parse_reply(&time_exceeded, true, 0x1234),
+            Some(IcmpReply { kind: IcmpKind::TimeExceeded, seq: 5 })
+        );
+        assert_eq!(parse_reply(&time_exceeded, true, 0x4321), None);
+        //our own requests to localhost and truncated packets are skipped
+        assert_eq!(parse_reply(&[&ip_header[..], &request].concat(), true, 0x1234), None);
+        assert_eq!(parse_reply(&raw_reply[..22], true, 0x1234), None);
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let stats = LatencyStats::from_samples(&[Some(1.0), None, Some(3.0), None]);
+        assert_eq!(stats.sent, 4);
+        assert_eq!(stats.received, 2);
+        assert_eq!(stats.loss_percent, 50.0);
+        assert_eq!(stats.min_ms, Some(1.0));
+        assert_eq!(stats.avg_ms, Some(2.0));
+        assert_eq!(stats.max_ms, Some(3.0));
+        assert_eq!(stats.stddev_ms, Some(1.0));
+        let lost = LatencyStats::from_samples(&[None]);
+        assert_eq!(lost.loss_percent, 100.0);
+        assert_eq!(lost.avg_ms, None);
+    }
+
+    #[test]
+    fn test_limits() {
+        assert!(resolve("10.64.64.1", true).is_ok());
+        assert!(resolve("255.255.255.255", true).is_err());
+        assert!(resolve("224.0.0.1", true).is_err());
+        assert!(resolve("0.0.0.0", true).is_err());
+        assert!(resolve("::1", true).is_err());
+        assert!(resolve("::1", false).is_ok());
+        assert!(check_duration(20, Duration::from_secs(3)).is_ok());
+        assert!(check_duration(20, Duration::from_secs(5)).is_err());
+
+        let guard = RunGuard::acquire().unwrap();
+        assert!(matches!(RunGuard::acquire(), Err(ShiitakeError::TooManyRequests(_))));
+        drop(guard);
+        assert!(RunGuard::acquire().is_ok());
+    }
+}
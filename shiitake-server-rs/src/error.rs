@@ -0,0 +1,220 @@
+use axum::{
+    body::HttpBody,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use thiserror::Error;
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::error!($($arg)*);
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ShiitakeError {
+    #[error("Failed to read file")]
+    FileReadError(#[from] std::io::Error),
+    #[error("Failed to parse int")]
+    ParseIntError(#[from] std::num::ParseIntError),
+    #[error("Failed to parse float")]
+    ParseFloatError(#[from] std::num::ParseFloatError),
+    #[error("Data not found")]
+    DataNotFound,
+    #[error("Bad request")]
+    BadRequest(String),
+    #[error("Not found")]
+    NotFound(String),
+    #[error("Forbidden")]
+    Forbidden(String),
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("System call failed")]
+    Errno(#[from] nix::errno::Errno),
+    #[error("Command failed")]
+    CommandFailed(String),
+    #[error("Too many requests")]
+    TooManyRequests(String),
+    #[error("Conflict")]
+    Conflict(String),
+    #[cfg(feature = "rio")]
+    #[error("Failed to access ini")]
+    IniError(#[from] crate::ini::IniError),
+    #[cfg(feature = "rio")]
+    #[error("Invalid setting")]
+    SchemaError(#[from] crate::rio_schema::SchemaError),
+}
+
+///the body of every error response
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    ///stable machine readable identifier for the kind of error
+    pub code: &'static str,
+    pub message: String,
+    pub detail: Option<String>,
+}
+
+impl ShiitakeError {
+    pub fn status(&self) -> StatusCode {
+        use nix::errno::Errno;
+        match self {
+            ShiitakeError::FileReadError(e) if e.kind() == std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ShiitakeError::FileReadError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ShiitakeError::ParseIntError(_) | ShiitakeError::ParseFloatError(_) => StatusCode::BAD_REQUEST,
+            ShiitakeError::DataNotFound => StatusCode::NOT_FOUND,
+            ShiitakeError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ShiitakeError::NotFound(_) => StatusCode::NOT_FOUND,
+            ShiitakeError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ShiitakeError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ShiitakeError::Errno(Errno::ESRCH) => StatusCode::NOT_FOUND,
+            ShiitakeError::Errno(Errno::EPERM | Errno::EACCES) => StatusCode::FORBIDDEN,
+            ShiitakeError::Errno(Errno::EINVAL) => StatusCode::BAD_REQUEST,
+            ShiitakeError::Errno(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ShiitakeError::CommandFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ShiitakeError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ShiitakeError::Conflict(_) => StatusCode::CONFLICT,
+            #[cfg(feature = "rio")]
+            ShiitakeError::IniError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            #[cfg(feature = "rio")]
+            ShiitakeError::SchemaError(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ShiitakeError::FileReadError(_) => "file_read",
+            ShiitakeError::ParseIntError(_) => "parse_int",
+            ShiitakeError::ParseFloatError(_) => "parse_float",
+            ShiitakeError::DataNotFound => "data_not_found",
+            ShiitakeError::BadRequest(_) => "bad_request",
+            ShiitakeError::NotFound(_) => "not_found",
+            ShiitakeError::Forbidden(_) => "forbidden",
+            ShiitakeError::Unauthorized => "unauthorized",
+            ShiitakeError::Errno(_) => "errno",
+            ShiitakeError::CommandFailed(_) => "command_failed",
+            ShiitakeError::TooManyRequests(_) => "too_many_requests",
+            ShiitakeError::Conflict(_) => "conflict",
+            #[cfg(feature = "rio")]
+            ShiitakeError::IniError(_) => "ini",
+            #[cfg(feature = "rio")]
+            ShiitakeError::SchemaError(_) => "invalid_setting",
+        }
+    }
+
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            ShiitakeError::FileReadError(e) => Some(e.to_string()),
+            ShiitakeError::ParseIntError(e) => Some(e.to_string()),
+            ShiitakeError::ParseFloatError(e) => Some(e.to_string()),
+            ShiitakeError::DataNotFound | ShiitakeError::Unauthorized => None,
+            ShiitakeError::BadRequest(detail)
+            | ShiitakeError::NotFound(detail)
+            | ShiitakeError::Forbidden(detail)
+            | ShiitakeError::CommandFailed(detail)
+            | ShiitakeError::TooManyRequests(detail)
+            | ShiitakeError::Conflict(detail) => Some(detail.clone()),
+            ShiitakeError::Errno(errno) => Some(errno.desc().to_string()),
+            #[cfg(feature = "rio")]
+            ShiitakeError::IniError(e) => Some(format!("{:?}", e)),
+            #[cfg(feature = "rio")]
+            ShiitakeError::SchemaError(e) => Some(e.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for ShiitakeError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!("{}: {:?}", self, self);
+        }
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            detail: self.detail(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+pub type ShiitakeResult<T> = Result<T, ShiitakeError>;
+
+fn client_error_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+        _ => "client_error",
+    }
+}
+
+///axum's extractor rejections and unmatched routes answer in plain text, this gives them the same body as every other error
+pub async fn json_client_errors(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if !response.status().is_client_error() || is_json {
+        return response;
+    }
+    let (parts, mut body) = response.into_parts();
+    let mut text = Vec::new();
+    while let Some(Ok(chunk)) = body.data().await {
+        text.extend_from_slice(&chunk);
+    }
+    let text = String::from_utf8_lossy(&text).trim().to_string();
+    let body = ErrorBody {
+        code: client_error_code(parts.status),
+        message: parts.status.canonical_reason().unwrap_or("Client error").to_string(),
+        detail: Some(text).filter(|text| !text.is_empty()),
+    };
+    let mut response = (parts.status, Json(body)).into_response();
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(ShiitakeError::BadRequest("bad hex".to_string()).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(ShiitakeError::Errno(nix::errno::Errno::ESRCH).status(), StatusCode::NOT_FOUND);
+        assert_eq!(ShiitakeError::Conflict("busy".to_string()).status(), StatusCode::CONFLICT);
+        let parse_error = "zz".parse::<i64>().unwrap_err();
+        let error = ShiitakeError::from(parse_error);
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.code(), "parse_int");
+        assert!(error.detail().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_json_client_errors() {
+        let rejection = (StatusCode::UNPROCESSABLE_ENTITY, "Failed to deserialize the JSON body").into_response();
+        let mut response = json_client_errors(rejection).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "unprocessable_entity");
+        assert_eq!(body["detail"], "Failed to deserialize the JSON body");
+
+        let not_found = json_client_errors(StatusCode::NOT_FOUND.into_response()).await;
+        assert_eq!(not_found.headers()[header::CONTENT_TYPE], "application/json");
+        let error = ShiitakeError::Forbidden("read-only".to_string()).into_response();
+        let mut response = json_client_errors(error).await;
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["code"], "forbidden");
+    }
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::stream::LIVE_FEED;
+use crate::types::HistoryEntry;
+
+///how often the background sampler records a `Stats` snapshot
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+///how many samples are kept before the oldest gets overwritten
+pub const DEFAULT_HISTORY_CAPACITY: usize = 300;
+
+///A fixed size buffer that overwrites its oldest element once full.
+///All the memory for the slots is allocated up front so the footprint doesn't grow over time.
+#[derive(Debug)]
+pub struct RingBuffer<T> {
+    buffer: Vec<T>,
+    capacity: usize,
+    head: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        RingBuffer {
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+            head: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.buffer.len() < self.capacity {
+            self.buffer.push(value);
+        } else {
+            self.buffer[self.head] = value;
+            self.head = (self.head + 1) % self.capacity;
+        }
+    }
+
+    ///iterates from the oldest element to the newest
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.buffer[self.head..].iter().chain(self.buffer[..self.head].iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+pub type StatsHistory = RingBuffer<HistoryEntry>;
+
+impl StatsHistory {
+    ///every entry recorded strictly after `since` (milliseconds since the unix epoch)
+    pub fn since(&self, since: u64) -> impl Iterator<Item = &HistoryEntry> {
+        self.iter().filter(move |entry| entry.timestamp > since)
+    }
+}
+
+///milliseconds since the unix epoch
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+///Samples `Stats` every `interval` into the global history buffer and the live stream feed.
+///Runs on the same low priority runtime thread as the request handlers.
+pub async fn run_sampler(interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut counters = None;
+    loop {
+        ticker.tick().await;
+        let stats = crate::measuring::measure_stats(&mut crate::SYSTEM.write(), &mut counters);
+        let entry = HistoryEntry {
+            timestamp: now_millis(),
+            stats,
+        };
+        if LIVE_FEED.wants_processes() {
+            LIVE_FEED.publish_processes(crate::measuring::measure_processes(&mut crate::SYSTEM.write()));
+        }
+        LIVE_FEED.publish_stats(entry.clone());
+        crate::HISTORY.write().push(entry);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RingBuffer;
+
+    #[test]
+    fn test_ring_buffer_wraps() {
+        let mut ring = RingBuffer::with_capacity(3);
+        for i in 0..5 {
+            ring.push(i);
+        }
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_ring_buffer_partial() {
+        let mut ring = RingBuffer::with_capacity(4);
+        ring.push(1);
+        ring.push(2);
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(ring.capacity(), 4);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{path::PathBuf, collections::HashMap, io::Write};
+use std::path::{Path, PathBuf};
 
 use thiserror::Error;
 
@@ -29,7 +29,7 @@ pub enum IniTypes {
 }
 
 impl IniTypes {
-    pub fn to_string(self) -> Option<String> {
+    pub fn into_string(self) -> Option<String> {
         match self {
             IniTypes::String(value) => Some(value),
             _ => None
@@ -83,32 +83,148 @@ impl IniTypes {
     }
 }
 
+impl std::fmt::Display for IniTypes {
+    ///how the value is written after the `=`, strings without their quotes
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IniTypes::String(value) => write!(f, "{}", value),
+            IniTypes::Integer(value) => write!(f, "{}", value),
+            IniTypes::Float(value) => write!(f, "{}", value),
+            IniTypes::Boolean(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> (IniTypes, bool) {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        (IniTypes::String(value[1..value.len()-1].to_string()), true)
+    } else if let Ok(value) = value.parse::<i64>() {
+        (IniTypes::Integer(value), false)
+    } else if let Ok(value) = value.parse::<f64>() {
+        (IniTypes::Float(value), false)
+    } else if let Ok(value) = value.parse::<bool>() {
+        (IniTypes::Boolean(value), false)
+    } else {
+        (IniTypes::String(value.to_string()), false)
+    }
+}
+
+///A `key=value` line.
+///The line is written back exactly as it was read until its value changes.
+#[derive(Debug, Clone)]
+struct IniEntry {
+    key: String,
+    value: IniTypes,
+    ///everything up to the start of the value, so a rewritten line keeps its spacing around the `=`
+    prefix: String,
+    quoted: bool,
+    raw: Option<String>,
+}
+
+impl IniEntry {
+    ///new entries follow NI's style of quoting every string with no spaces around the `=`
+    fn new(key: &str, value: IniTypes) -> Self {
+        IniEntry {
+            key: key.to_string(),
+            prefix: format!("{}=", key),
+            quoted: matches!(value, IniTypes::String(_)),
+            value,
+            raw: None,
+        }
+    }
+
+    fn set(&mut self, value: IniTypes) {
+        if self.value != value {
+            //an unquoted string that now needs quoting to be read back as a string gets quoted
+            if let IniTypes::String(string) = &value {
+                self.quoted |= !matches!(parse_value(string).0, IniTypes::String(_)) || string.trim() != string;
+            }
+            self.value = value;
+            self.raw = None;
+        }
+    }
+
+    fn render(&self) -> String {
+        if let Some(raw) = &self.raw {
+            return raw.clone();
+        }
+        match &self.value {
+            IniTypes::String(value) if self.quoted => format!("{}\"{}\"", self.prefix, value),
+            value => format!("{}{}", self.prefix, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum IniLine {
+    Entry(IniEntry),
+    ///comments, blank lines and anything else that isn't a key, kept verbatim
+    Raw(String),
+}
+
+impl IniLine {
+    fn is_blank(&self) -> bool {
+        matches!(self, IniLine::Raw(raw) if raw.trim().is_empty())
+    }
+}
+
+///A parsed INI file that remembers its layout.
+///Sections, keys, comments, quoting and whitespace come back out in the same order they were read,
+///only lines whose values changed are rewritten.
 #[derive(Debug, Clone)]
 pub struct Ini {
     path: PathBuf,
-    sections: HashMap<String, IniSection>,
+    ///the lines before the first header are kept in a nameless section without a header
+    sections: Vec<IniSection>,
+    newline: &'static str,
+    trailing_newline: bool,
 }
 
 impl Ini {
     pub fn get(&self, section: &str) -> Option<&IniSection> {
-        self.sections.get(section)
+        self.sections.iter().find(|s| s.header.is_some() && s.name == section)
     }
 
     pub fn get_mut(&mut self, section: &str) -> Option<&mut IniSection> {
-        self.sections.get_mut(section)
+        self.sections.iter_mut().find(|s| s.header.is_some() && s.name == section)
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = &IniSection> {
+        self.sections.iter().filter(|s| s.header.is_some())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     pub fn set(&mut self, section: IniSection) -> IniResult<()> {
-        if self.sections.contains_key(&section.name) {
+        if self.get(&section.name).is_some() {
             Err(IniError::CustomError("Section already exists"))
         } else {
-            self.sections.insert(section.name.clone(), section);
+            self.push_section(section);
             Ok(())
         }
     }
 
+    ///replaces the keys of an existing section in place, or appends the section if it's new
     pub fn create_and_set(&mut self, section: IniSection) {
-        self.sections.insert(section.name.clone(), section);
+        match self.get_mut(&section.name) {
+            Some(existing) => existing.replace_entries(section),
+            None => self.push_section(section),
+        }
+    }
+
+    fn push_section(&mut self, mut section: IniSection) {
+        if section.header.is_none() {
+            section.header = Some(format!("[{}]", section.name));
+        }
+        //keep a blank line between sections like NI's files do
+        if let Some(last) = self.sections.last_mut() {
+            if !last.lines.is_empty() && !last.lines.last().is_some_and(IniLine::is_blank) {
+                last.lines.push(IniLine::Raw(String::new()));
+            }
+        }
+        self.sections.push(section);
     }
 
     pub fn save(&self) -> Result<(), IniError> {
@@ -120,66 +236,176 @@ impl Ini {
     }
 }
 
+impl std::fmt::Display for Ini {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines = self.sections.iter().flat_map(|section| {
+            section
+                .header
+                .iter()
+                .cloned()
+                .chain(section.lines.iter().map(|line| match line {
+                    IniLine::Entry(entry) => entry.render(),
+                    IniLine::Raw(raw) => raw.clone(),
+                }))
+        });
+        for (i, line) in lines.enumerate() {
+            if i > 0 {
+                f.write_str(self.newline)?;
+            }
+            f.write_str(&line)?;
+        }
+        if self.trailing_newline {
+            f.write_str(self.newline)?;
+        }
+        Ok(())
+    }
+}
+
 
 #[derive(Debug, Clone)]
 pub struct IniSection {
     name: String,
-    keys: HashMap<String, IniTypes>,
+    ///the header line as read, `None` for the lines before the first header
+    header: Option<String>,
+    lines: Vec<IniLine>,
 }
 
 impl IniSection {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entry(&self, key: &str) -> Option<&IniEntry> {
+        self.lines.iter().find_map(|line| match line {
+            IniLine::Entry(entry) if entry.key == key => Some(entry),
+            _ => None,
+        })
+    }
+
+    fn entry_mut(&mut self, key: &str) -> Option<&mut IniEntry> {
+        self.lines.iter_mut().find_map(|line| match line {
+            IniLine::Entry(entry) if entry.key == key => Some(entry),
+            _ => None,
+        })
+    }
+
     pub fn get(&self, key: &str) -> Option<&IniTypes> {
-        self.keys.get(key)
+        self.entry(key).map(|entry| &entry.value)
+    }
+
+    ///every key and value in file order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &IniTypes)> {
+        self.lines.iter().filter_map(|line| match line {
+            IniLine::Entry(entry) => Some((entry.key.as_str(), &entry.value)),
+            IniLine::Raw(_) => None,
+        })
     }
 
     pub fn set(&mut self, key: &str, value: IniTypes) -> IniResult<()>{
-        if self.keys.contains_key(key) {
-            self.keys.insert(key.to_string(), value);
-            Ok(())
-        } else {
-            Err(IniError::KeyDoesntExist)
+        match self.entry_mut(key) {
+            Some(entry) => {
+                entry.set(value);
+                Ok(())
+            }
+            None => Err(IniError::KeyDoesntExist),
         }
     }
 
+    ///new keys go after the last existing key so trailing comments and blank lines stay put
     pub fn create_and_set(&mut self, key: &str, value: IniTypes) {
-        self.keys.insert(key.to_string(), value);
+        if let Some(entry) = self.entry_mut(key) {
+            entry.set(value);
+            return;
+        }
+        let position = self
+            .lines
+            .iter()
+            .rposition(|line| matches!(line, IniLine::Entry(_)))
+            .map_or(0, |i| i + 1);
+        self.lines.insert(position, IniLine::Entry(IniEntry::new(key, value)));
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<IniTypes> {
+        let index = self
+            .lines
+            .iter()
+            .position(|line| matches!(line, IniLine::Entry(entry) if entry.key == key))?;
+        match self.lines.remove(index) {
+            IniLine::Entry(entry) => Some(entry.value),
+            IniLine::Raw(_) => None,
+        }
+    }
+
+    ///makes this section's keys match `other`'s, leaving untouched keys and comments where they are
+    fn replace_entries(&mut self, other: IniSection) {
+        let keep = other.entries().map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+        self.lines.retain(|line| match line {
+            IniLine::Entry(entry) => keep.contains(&entry.key),
+            IniLine::Raw(_) => true,
+        });
+        for line in other.lines {
+            if let IniLine::Entry(entry) = line {
+                self.create_and_set(&entry.key, entry.value);
+            }
+        }
     }
 
     pub fn new(name: &str) -> IniSection {
-        IniSection{name: name.to_string(), keys: HashMap::new()}
+        IniSection{name: name.to_string(), header: None, lines: Vec::new()}
     }
 }
 
-pub fn read_ini(path: PathBuf) -> IniResult<Ini> {
-    let file = std::fs::read_to_string(path.clone())?;
-    let mut sections = HashMap::new();
-    let mut current_section = String::new();
+pub fn parse_ini(path: PathBuf, file: &str) -> Ini {
+    let mut sections = vec![IniSection::new("")];
     for line in file.lines() {
-        if line.starts_with('[') && line.ends_with(']') {
-            current_section = line[1..line.len()-1].to_string();
-            sections.insert(current_section.clone(), IniSection { name: current_section.clone(), keys: HashMap::new() });
-        } else if let Some((key, value)) = line.replace(" ", "").split_once('=') {
-            if value.starts_with('"') && value.ends_with('"') {
-                sections.get_mut(&current_section).unwrap().keys.insert(key.to_string(), IniTypes::String(value[1..value.len()-1].to_string()));
-            } else if value.parse::<i64>().is_ok() {
-                sections.get_mut(&current_section).unwrap().keys.insert(key.to_string(), IniTypes::Integer(value.parse::<i64>().unwrap()));
-            } else if value.parse::<f64>().is_ok() {
-                sections.get_mut(&current_section).unwrap().keys.insert(key.to_string(), IniTypes::Float(value.parse::<f64>().unwrap()));
-            } else if value == "true" || value == "false" {
-                sections.get_mut(&current_section).unwrap().keys.insert(key.to_string(), IniTypes::Boolean(value.parse::<bool>().unwrap()));
-            } else {
-                sections.get_mut(&current_section).unwrap().keys.insert(key.to_string(), IniTypes::String(value.to_string()));
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            sections.push(IniSection {
+                name: trimmed[1..trimmed.len()-1].trim().to_string(),
+                header: Some(line.to_string()),
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        let is_comment = trimmed.starts_with(';') || trimmed.starts_with('#');
+        let parsed = line.split_once('=').filter(|_| !is_comment).map(|(key, value)| {
+            let (value, quoted) = parse_value(value.trim());
+            let value_start = line.len() - line.split_once('=').unwrap().1.trim_start().len();
+            IniEntry {
+                key: key.trim().to_string(),
+                value,
+                prefix: line[..value_start].to_string(),
+                quoted,
+                raw: Some(line.to_string()),
             }
+        });
+        let section = sections.last_mut().unwrap();
+        match parsed {
+            Some(entry) => section.lines.push(IniLine::Entry(entry)),
+            None => section.lines.push(IniLine::Raw(line.to_string())),
         }
     }
-    Ok(Ini{sections, path})
+    if sections[0].lines.is_empty() {
+        sections.remove(0);
+    }
+    Ini {
+        path,
+        sections,
+        newline: if file.contains("\r\n") { "\r\n" } else { "\n" },
+        trailing_newline: file.ends_with('\n') || file.is_empty(),
+    }
+}
+
+pub fn read_ini(path: PathBuf) -> IniResult<Ini> {
+    let file = std::fs::read_to_string(&path)?;
+    Ok(parse_ini(path, &file))
 }
 
 pub fn read_ini_field(path: PathBuf, section: &str, key: &str) -> IniResult<IniTypes> {
     let ini = read_ini(path)?;
     if let Some(section) = ini.get(section) {
         if let Some(key) = section.get(key) {
-            return Ok(key.clone());
+            Ok(key.clone())
         } else {
             Err(IniError::KeyDoesntExist)
         }
@@ -189,18 +415,63 @@ pub fn read_ini_field(path: PathBuf, section: &str, key: &str) -> IniResult<IniT
 }
 
 pub fn write_ini(ini: Ini) -> IniResult<()> {
-    let mut file = std::fs::File::create(ini.path)?;
-    for section in ini.sections.values() {
-        file.write_all(format!("[{}]\n", section.name).as_bytes())?;
-        for (key, value) in section.keys.iter() {
-            match value {
-                IniTypes::String(value) => file.write_all(format!("{} = \"{}\"\n", key, value).as_bytes())?,
-                IniTypes::Integer(value) => file.write_all(format!("{} = {}\n", key, value).as_bytes())?,
-                IniTypes::Float(value) => file.write_all(format!("{} = {}\n", key, value).as_bytes())?,
-                IniTypes::Boolean(value) => file.write_all(format!("{} = {}\n", key, value).as_bytes())?
-            }
-        }
-        file.write_all(b"\n")?;
-    }
+    crate::backups::write_config_file(&ini.path, ini.to_string().as_bytes())?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NI_RT: &str = "[systemsettings]\r\nhost_name=\"6463-Remote-Rio\"\r\n; set by the imaging tool\r\nComment=\"a b  c\"\r\n\r\n[Default]\r\nPort = 80\r\nSSLEnabled = false\r\nServerName = Default Host\r\n";
+
+    fn parse(file: &str) -> Ini {
+        parse_ini(PathBuf::from("test.ini"), file)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ini = parse(NI_RT);
+        assert_eq!(ini.to_string(), NI_RT);
+        assert_eq!(ini.get("Default").unwrap().get("Port").unwrap(), &IniTypes::Integer(80));
+        assert_eq!(ini.get("Default").unwrap().get("SSLEnabled").unwrap(), &IniTypes::Boolean(false));
+        assert_eq!(ini.get("Default").unwrap().get("ServerName").unwrap(), &IniTypes::String("Default Host".to_string()));
+        assert_eq!(ini.get("systemsettings").unwrap().get("Comment").unwrap(), &IniTypes::String("a b  c".to_string()));
+
+        let no_trailing_newline = "top=1\n[a]\nb = \"c\"";
+        assert_eq!(parse(no_trailing_newline).to_string(), no_trailing_newline);
+    }
+
+    #[test]
+    fn test_only_changed_lines() {
+        let mut ini = parse(NI_RT);
+        let section = ini.get_mut("Default").unwrap();
+        section.set("Port", IniTypes::Integer(8080)).unwrap();
+        section.set("SSLEnabled", IniTypes::Boolean(false)).unwrap();
+        section.set("ServerName", IniTypes::String("42".to_string())).unwrap();
+        section.create_and_set("Timeout", IniTypes::String("30 s".to_string()));
+        assert!(section.set("Missing", IniTypes::Integer(1)).is_err());
+        assert_eq!(
+            ini.to_string(),
+            NI_RT
+                .replace("Port = 80", "Port = 8080")
+                .replace("ServerName = Default Host", "ServerName = \"42\"\r\nTimeout=\"30 s\"")
+        );
+    }
+
+    #[test]
+    fn test_replace_section() {
+        let mut ini = parse("[eth0]\ndhcpenabled=\"1\"\n;dhcp lease\ndhcpipaddr=\"10.64.60.53\"\n\n[usb0]\ndhcpenabled=\"1\"\n");
+        let mut eth0 = IniSection::new("eth0");
+        eth0.create_and_set("dhcpenabled", IniTypes::String("0".to_string()));
+        eth0.create_and_set("IP_Address", IniTypes::String("10.64.64.2".to_string()));
+        ini.create_and_set(eth0);
+        let mut wlan0 = IniSection::new("wlan0");
+        wlan0.create_and_set("dhcpenabled", IniTypes::String("1".to_string()));
+        ini.create_and_set(wlan0);
+        assert_eq!(
+            ini.to_string(),
+            "[eth0]\ndhcpenabled=\"0\"\nIP_Address=\"10.64.64.2\"\n;dhcp lease\n\n[usb0]\ndhcpenabled=\"1\"\n\n[wlan0]\ndhcpenabled=\"1\"\n"
+        );
+    }
+}
@@ -1,34 +1,61 @@
 #![feature(thread_local)]
+pub mod auth;
+pub mod backups;
+pub mod config;
+pub mod diagnostics;
+pub mod error;
+pub mod history;
 pub mod measuring;
+pub mod metrics;
+pub mod network;
+pub mod process_control;
+pub mod process_filter;
+pub mod procfs;
+pub mod stream;
 pub mod system;
+pub mod sysfs;
 pub mod types;
 mod webpage;
+#[cfg(test)]
+mod test_util;
 
-use std::process::Command;
-
-use measuring::{measure_processes, measure_stats};
+use measuring::{latest_stats, measure_processes};
 
 use axum::{
+    extract::{Path, Query},
+    middleware,
     routing::{get, post},
     Json, Router,
 };
-use crate::types::{routes, Processes, Stats, Summary};
+pub use crate::error::{ShiitakeError, ShiitakeResult};
+use crate::history::StatsHistory;
+use crate::types::{
+    routes, HistoryEntry, ProcessDetail, ProcessTreeNode, Processes, Stats, Summary, ThreadInfo,
+};
 use static_init::dynamic;
 use sysinfo::{System, SystemExt};
 
-#[cfg(target_vendor = "roborio")]
+#[cfg(feature = "rio")]
 pub mod rio_interface;
-#[cfg(target_vendor = "roborio")]
+#[cfg(feature = "rio")]
 pub mod ini;
+#[cfg(feature = "rio")]
+pub mod rio_schema;
 
 #[cfg(not(target_os = "linux"))]
 compile_error!("This program is only supported on Linux");
+#[cfg(all(target_vendor = "roborio", not(feature = "rio")))]
+compile_error!("Build for the roboRIO with `--features rio`");
 
 #[dynamic]
 #[thread_local]
 static mut SYSTEM: System = System::new_all();
-#[dynamic]
+//lazy so the Rio root from the config is known by the time it's read
+#[dynamic(lazy)]
 static SUMMARY: Summary = system::make_summary();
+#[dynamic]
+#[thread_local]
+static mut HISTORY: StatsHistory = StatsHistory::with_capacity(config::get().sampling.history_capacity);
 
 macro_rules! info {
     ($($arg:tt)*) => {
@@ -37,139 +64,288 @@ macro_rules! info {
     }
 }
 
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::warn!($($arg)*);
+    }
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
+    let args = match config::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        print!("{}", config::USAGE);
+        return;
+    }
+    if args.hash_password {
+        print_password_hash();
+        return;
+    }
+
+    let loaded = config::Config::load(args.config_path()).and_then(|mut config| {
+        config.apply_args(&args);
+        config.validate()?;
+        Ok(config)
+    });
+    match loaded {
+        Ok(config) => config::set(config),
+        Err(e) => {
+            eprintln!("{}: {}", args.config_path().display(), e);
+            std::process::exit(1);
+        }
+    }
+    let config = config::get();
+
     #[cfg(feature = "logging")]
     {
         tracing_subscriber::fmt::fmt()
-            .with_max_level(tracing::Level::TRACE)
+            .with_max_level(config.server.log_level.parse::<tracing::Level>().unwrap_or(tracing::Level::INFO))
             .init();
     }
 
-    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 80));
-    //create addr of local host and port 80
-    // let localhost_addr = std::net::SocketAddr::from(([127, 0, 0, 1], 80));
+    if !config.auth.required() {
+        if config.auth.allow_unauthenticated {
+            warn!("No api_key or password_hash configured, mutating routes are open to anyone on the network");
+        } else {
+            warn!("No api_key or password_hash configured, only GET requests will be served");
+        }
+    }
+
+    #[cfg(feature = "rio")]
+    if let Err(e) = rio_interface::load_rio_data(&config.rio.root) {
+        eprintln!("Failed to read the Rio's config files under {}: {:?}", config.rio.root.display(), e);
+        std::process::exit(1);
+    }
+
+    let app = make_router(&config.server);
+
+    //validated above
+    if let Ok(priority) = config.server.thread_priority.to_thread_priority() {
+        thread_priority::set_current_thread_priority(priority)
+            .expect("Failed to set thread priority");
+    }
+
+    tokio::spawn(history::run_sampler(config.sampling.interval()));
+
+    info!("Router made, starting server");
+
+    let mut servers = tokio::task::JoinSet::new();
+    for addr in &config.server.listen {
+        let server = bind_listener(*addr)
+            .map_err(|e| e.to_string())
+            .and_then(|listener| axum::Server::from_tcp(listener).map_err(|e| e.to_string()));
+        match server {
+            Ok(server) => {
+                info!("Listening on {}", addr);
+                servers.spawn(server.serve(app.clone().into_make_service()));
+            }
+            Err(e) => {
+                eprintln!("Failed to listen on {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    while let Some(result) = servers.join_next().await {
+        result
+            .expect("Server task panicked")
+            .expect("Server failed");
+    }
+}
+
+fn make_router(server: &config::ServerConfig) -> Router {
+    use config::RouteGroup;
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route(routes::ROOT, get(root))
-        .route(routes::STATS, get(all_stats))
-        .route(routes::PROCESSES, get(processes))
         .route(routes::SYSTEM_SUMMARY, get(system_summary))
-        .route(routes::TIME, get(get_time))
-        .route(routes::TIME, post(set_time))
-        .route(routes::REBOOT, post(reboot))
-        .route(routes::UPTIME, get(get_uptime))
-        .route(routes::SET_IP, post(set_static_ip))
-        ;
+        .route(routes::UPTIME, get(get_uptime));
+    if server.enabled(RouteGroup::Stats) {
+        app = app
+            .route(routes::STATS, get(all_stats))
+            .route(routes::STATS_HISTORY, get(stats_history))
+            .route(routes::STATS_STREAM, get(stream::stats_stream));
+    }
+    if server.enabled(RouteGroup::Metrics) {
+        app = app.route(routes::METRICS, get(metrics::metrics));
+    }
+    if server.enabled(RouteGroup::Processes) {
+        app = app
+            .route(routes::PROCESSES, get(processes))
+            .route(routes::PROCESS_TREE, get(process_tree))
+            .route(routes::PROCESS_DETAIL, get(process_detail))
+            .route(routes::PROCESS_THREADS, get(process_threads));
+    }
+    if server.enabled(RouteGroup::ProcessControl) {
+        app = process_control::init_process_control(app);
+    }
+    if server.enabled(RouteGroup::Time) {
+        app = app
+            .route(routes::TIME, get(get_time))
+            .route(routes::TIME, post(set_time));
+    }
+    if server.enabled(RouteGroup::Reboot) {
+        app = app.route(routes::REBOOT, post(reboot));
+    }
+    if server.enabled(RouteGroup::Network) {
+        app = network::init_network(app);
+    }
+    if server.enabled(RouteGroup::Backups) {
+        app = backups::init_backups(app);
+    }
+    if server.enabled(RouteGroup::Diagnostics) {
+        app = diagnostics::init_diagnostics(app);
+    }
+    #[cfg(feature = "rio")]
+    if server.enabled(RouteGroup::Rio) {
+        app = rio_interface::init_rio(app);
+    }
+    auth::init_auth(app)
+        .layer(middleware::from_fn(auth::require_auth))
+        .layer(middleware::map_response(error::json_client_errors))
+}
 
-    thread_priority::set_current_thread_priority(thread_priority::ThreadPriority::Min)
-        .expect("Failed to set thread priority");
+///binds a listening socket, ipv6 sockets are v6 only so `0.0.0.0` and `[::]` can share a port
+fn bind_listener(addr: std::net::SocketAddr) -> nix::Result<std::net::TcpListener> {
+    use nix::sys::socket::{self, sockopt, AddressFamily, Backlog, SockFlag, SockType, SockaddrStorage};
+    use std::os::fd::AsRawFd;
 
-    info!("Router made, starting server");
+    let family = if addr.is_ipv6() { AddressFamily::Inet6 } else { AddressFamily::Inet };
+    let fd = socket::socket(family, SockType::Stream, SockFlag::SOCK_CLOEXEC, None)?;
+    socket::setsockopt(&fd, sockopt::ReuseAddr, &true)?;
+    if addr.is_ipv6() {
+        socket::setsockopt(&fd, sockopt::Ipv6V6Only, &true)?;
+    }
+    socket::bind(fd.as_raw_fd(), &SockaddrStorage::from(addr))?;
+    socket::listen(&fd, Backlog::new(1024)?)?;
+    Ok(std::net::TcpListener::from(fd))
+}
 
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .expect("Server failed");
+///reads a password from stdin and prints the `password_hash` to put in the config file
+fn print_password_hash() {
+    let mut password = String::new();
+    if std::io::stdin().read_line(&mut password).is_err() {
+        eprintln!("Failed to read password from stdin");
+        std::process::exit(1);
+    }
+    match auth::hash_password(password.trim_end_matches(['\r', '\n'])) {
+        Ok(hash) => println!("{}", hash),
+        Err(e) => {
+            eprintln!("Failed to hash password: {:?}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
 async fn root() -> webpage::Webpage {
     webpage::Webpage
 }
 
-use thiserror::Error;
-
 async fn all_stats() -> Json<Stats> {
-    Json(measure_stats(&mut SYSTEM.write()))
+    Json(latest_stats())
 }
 
-async fn processes() -> Json<Processes> {
-    Json(measure_processes(&mut SYSTEM.write()))
+#[derive(serde::Deserialize)]
+struct HistoryQuery {
+    since: Option<u64>,
+    fields: Option<String>,
+}
+
+async fn stats_history(Query(query): Query<HistoryQuery>) -> Json<Vec<HistoryEntry>> {
+    let fields = query.fields.as_deref().map(|fields| fields.split(',').collect::<Vec<_>>());
+    let history = HISTORY.read();
+    Json(
+        history
+            .since(query.since.unwrap_or(0))
+            .cloned()
+            .map(|mut entry| {
+                if let Some(fields) = &fields {
+                    entry.stats.retain_fields(fields);
+                }
+                entry
+            })
+            .collect(),
+    )
+}
+
+async fn processes(Query(query): Query<process_filter::ProcessQuery>) -> ShiitakeResult<Json<Processes>> {
+    let processes = measure_processes(&mut SYSTEM.write());
+    process_filter::apply_process_query(processes, &query)
+        .map(Json)
+        .map_err(|e| ShiitakeError::BadRequest(e.to_string()))
+}
+
+async fn process_detail(Path(pid): Path<u32>) -> ShiitakeResult<Json<ProcessDetail>> {
+    Ok(Json(measuring::measure_process_detail(pid)?))
+}
+
+async fn process_tree() -> Json<Vec<ProcessTreeNode>> {
+    Json(measuring::measure_process_tree(&mut SYSTEM.write()))
+}
+
+async fn process_threads(Path(pid): Path<u32>) -> ShiitakeResult<Json<Vec<ThreadInfo>>> {
+    let cpu_count = SYSTEM.read().cpus().len();
+    Ok(Json(measuring::measure_threads(pid, cpu_count)?))
 }
 
 async fn system_summary() -> Json<Summary> {
-    Json(SUMMARY.clone())
+    let mut summary = SUMMARY.clone();
+    //addresses change at runtime, the team with them
+    summary.team = network::team::current_team(&summary.hostname);
+    Json(summary)
 }
 
-async fn get_time() -> String {
+async fn get_time() -> ShiitakeResult<String> {
     use crate::types::timespec_to_hex;
 
-    let time_spec = nix::time::clock_gettime(nix::time::ClockId::CLOCK_REALTIME)
-        .expect("Failed to get time");
+    let time_spec = nix::time::clock_gettime(nix::time::ClockId::CLOCK_REALTIME)?;
     //if pointer width is 32, then the timespec is 32 bits, otherwise it's 64
     #[cfg(target_pointer_width = "32")]
     {
-        return timespec_to_hex(time_spec.tv_sec() as i64, time_spec.tv_nsec() as i64);
+        Ok(timespec_to_hex(time_spec.tv_sec() as i64, time_spec.tv_nsec() as i64))
     }
     #[cfg(target_pointer_width = "64")]
     {
-        return timespec_to_hex(time_spec.tv_sec(), time_spec.tv_nsec());
+        Ok(timespec_to_hex(time_spec.tv_sec(), time_spec.tv_nsec()))
     }
 }
 
-async fn set_time(hex: String) -> &'static str {
+async fn set_time(hex: String) -> ShiitakeResult<&'static str> {
     use crate::types::hex_to_timespec;
 
-    let decoded_timespec = hex_to_timespec(hex);
+    let decoded_timespec = hex_to_timespec(hex)?;
     #[cfg(target_pointer_width = "32")]
     {
         nix::time::clock_settime(
             nix::time::ClockId::CLOCK_REALTIME,
             nix::sys::time::TimeSpec::new(decoded_timespec.0 as i32, decoded_timespec.1 as i32),
-        )
-        .expect("Failed to set time");
+        )?;
     }
     #[cfg(target_pointer_width = "64")]
     {
         nix::time::clock_settime(
             nix::time::ClockId::CLOCK_REALTIME,
             nix::sys::time::TimeSpec::new(decoded_timespec.0, decoded_timespec.1),
-        )
-        .expect("Failed to set time");
+        )?;
     }
-    "Time set"
+    Ok("Time set")
 }
 
 async fn get_uptime() -> String {
     crate::types::timespec_to_hex(SYSTEM.read().uptime() as i64, 0)
 }
 
-async fn reboot(verification: String) -> &'static str {
+async fn reboot(verification: String) -> ShiitakeResult<&'static str> {
     if verification != crate::types::REBOOT_VERIFICATION {
-        return "Verification string incorrect";
+        return Err(ShiitakeError::Forbidden("Verification string incorrect".to_string()));
     }
     nix::sys::reboot::reboot(nix::sys::reboot::RebootMode::RB_AUTOBOOT)
-        .expect("Failed to reboot");
-    "Rebooting"
-}
-
-#[derive(serde::Deserialize)]
-struct StaticIpConfig {
-    interface: String,
-    ip: String,
-    gateway: String,
-}
-
-async fn set_static_ip(Json(config): Json<StaticIpConfig>) -> &'static str {
-    #[cfg(target_vendor = "roborio")]
-    {
-        rio_interface::write_static_ip(config.ip.clone(), config.gateway.clone(), config.gateway);
-    }
-    Command::new("ip")
-        .args(&["addr", "add", &format!("{}/24", config.ip), "dev", &config.interface])
-        .output()
-        .expect("Failed to set IP");
-    "Static IP set"
-}
-
-#[derive(Debug, Error)]
-enum ShiitakeError {
-    #[error("Failed to read file")]
-    FileReadError(#[from] std::io::Error),
-    #[error("Failed to parse int")]
-    ParseIntError(#[from] std::num::ParseIntError),
-    #[error("Failed to parse float")]
-    ParseFloatError(#[from] std::num::ParseFloatError),
-    #[error("Data not found")]
-    DataNotFound,
+        .map(|_| "Rebooting")
+        .map_err(ShiitakeError::from)
 }
@@ -1,35 +1,170 @@
 
 
-use crate::types::{DiskUsageEntry, NetworkUsageEntry, Process, Processes, Stats};
+use std::{collections::HashMap, path::{Path, PathBuf}, time::Instant};
 
-use sysinfo::{CpuExt, DiskExt, NetworkExt, PidExt, ProcessExt, System, SystemExt};
+use crate::procfs::{self, DiskStatCounters, KernelCounters, NetDevCounters, DISKSTAT_SECTOR_SIZE};
+use crate::sysfs;
+use crate::types::{
+    DiskIoEntry, DiskUsageEntry, LoadStats, NetworkUsageEntry, Process, ProcessDetail, ProcessTreeNode,
+    Processes, Stats, ThreadInfo,
+};
+use crate::ShiitakeError;
 
-pub fn measure_stats(system: &mut System) -> Stats {
+use static_init::dynamic;
+use sysinfo::{CpuExt, DiskExt, PidExt, ProcessExt, System, SystemExt};
+
+///The cumulative counters from the previous call to `measure_stats`.
+///Only the background sampler keeps one, so rates don't depend on how often (or how many) clients poll.
+pub struct CounterSnapshot {
+    taken: Instant,
+    network: HashMap<String, NetDevCounters>,
+    disks: HashMap<String, DiskStatCounters>,
+    kernel: KernelCounters,
+}
+
+fn per_sec(current: u64, previous: u64, elapsed: f64) -> f64 {
+    if elapsed <= 0.0 {
+        return 0.0;
+    }
+    current.saturating_sub(previous) as f64 / elapsed
+}
+
+fn network_entry(name: &str, current: &NetDevCounters, previous: Option<&NetDevCounters>, elapsed: f64) -> NetworkUsageEntry {
+    let previous = previous.copied().unwrap_or(*current);
+    NetworkUsageEntry {
+        interface: name.to_string(),
+        rx: current.rx_bytes.saturating_sub(previous.rx_bytes),
+        tx: current.tx_bytes.saturating_sub(previous.tx_bytes),
+        rx_bytes_per_sec: per_sec(current.rx_bytes, previous.rx_bytes, elapsed),
+        tx_bytes_per_sec: per_sec(current.tx_bytes, previous.tx_bytes, elapsed),
+        rx_packets_per_sec: per_sec(current.rx_packets, previous.rx_packets, elapsed),
+        tx_packets_per_sec: per_sec(current.tx_packets, previous.tx_packets, elapsed),
+        total_rx: current.rx_bytes,
+        total_tx: current.tx_bytes,
+        total_rx_packets: current.rx_packets,
+        total_tx_packets: current.tx_packets,
+        rx_errors: current.rx_errors,
+        tx_errors: current.tx_errors,
+        rx_dropped: current.rx_dropped,
+        tx_dropped: current.tx_dropped,
+    }
+}
+
+fn disk_io_entry(name: &str, current: &DiskStatCounters, previous: Option<&DiskStatCounters>, elapsed: f64) -> DiskIoEntry {
+    let previous = previous.copied().unwrap_or(*current);
+    DiskIoEntry {
+        device: name.to_string(),
+        read_bytes_per_sec: per_sec(current.sectors_read, previous.sectors_read, elapsed) * DISKSTAT_SECTOR_SIZE as f64,
+        write_bytes_per_sec: per_sec(current.sectors_written, previous.sectors_written, elapsed) * DISKSTAT_SECTOR_SIZE as f64,
+        read_iops: per_sec(current.reads_completed, previous.reads_completed, elapsed),
+        write_iops: per_sec(current.writes_completed, previous.writes_completed, elapsed),
+        total_read: current.sectors_read * DISKSTAT_SECTOR_SIZE,
+        total_written: current.sectors_written * DISKSTAT_SECTOR_SIZE,
+    }
+}
+
+fn load_stats(current: &KernelCounters, previous: Option<&KernelCounters>, elapsed: f64) -> LoadStats {
+    let previous = previous.copied().unwrap_or(*current);
+    let (load_1, load_5, load_15) = procfs::read_proc_file("/proc/loadavg")
+        .ok()
+        .and_then(|file| procfs::parse_loadavg(&file))
+        .unwrap_or_default();
+    let pressure = |path: &str| {
+        procfs::read_proc_file(path)
+            .ok()
+            .and_then(|file| procfs::parse_pressure(&file))
+    };
+    LoadStats {
+        load_1,
+        load_5,
+        load_15,
+        running: current.procs_running,
+        blocked: current.procs_blocked,
+        context_switches_per_sec: per_sec(current.context_switches, previous.context_switches, elapsed),
+        interrupts_per_sec: per_sec(current.interrupts, previous.interrupts, elapsed),
+        cpu_pressure: pressure("/proc/pressure/cpu"),
+        io_pressure: pressure("/proc/pressure/io"),
+    }
+}
+
+///fills in the network, block device and kernel rates, then stores the counters for the next call
+fn measure_rates(stats: &mut Stats, previous_counters: &mut Option<CounterSnapshot>) {
+    let now = Instant::now();
+    let network = procfs::read_proc_file("/proc/net/dev")
+        .map(|file| procfs::parse_net_dev(&file))
+        .unwrap_or_default();
+    let disks = procfs::read_proc_file("/proc/diskstats")
+        .map(|file| procfs::parse_diskstats(&file))
+        .unwrap_or_default();
+    let kernel = procfs::read_proc_file("/proc/stat")
+        .map(|file| procfs::parse_stat(&file))
+        .unwrap_or_default();
+
+    let previous = previous_counters.as_ref();
+    let elapsed = previous
+        .map(|snapshot| now.duration_since(snapshot.taken).as_secs_f64())
+        .unwrap_or(0.0);
+
+    stats.network_usage = Some(
+        network
+            .iter()
+            .map(|(name, counters)| {
+                network_entry(name, counters, previous.and_then(|snapshot| snapshot.network.get(name)), elapsed)
+            })
+            .collect::<Vec<_>>(),
+    );
+    stats.disk_io = Some(
+        disks
+            .iter()
+            .map(|(name, counters)| {
+                disk_io_entry(name, counters, previous.and_then(|snapshot| snapshot.disks.get(name)), elapsed)
+            })
+            .collect::<Vec<_>>(),
+    );
+    stats.load = Some(load_stats(&kernel, previous.map(|snapshot| &snapshot.kernel), elapsed));
+
+    *previous_counters = Some(CounterSnapshot {
+        taken: now,
+        network: network.into_iter().collect(),
+        disks: disks.into_iter().collect(),
+        kernel,
+    });
+}
+
+///rates are measured since `counters`, which is then replaced with the current ones
+pub fn measure_stats(system: &mut System, counters: &mut Option<CounterSnapshot>) -> Stats {
     let mut stats: Stats = Default::default();
 
     system.refresh_cpu();
     system.refresh_memory();
-    system.refresh_networks();
     system.refresh_disks();
 
     let cpus = system.cpus();
 
     stats.cpu_speed = Some(cpus.iter().map(|cpu| cpu.frequency() * 1_000_000).collect::<Vec<_>>());
     stats.cpu_usage = Some(cpus.iter().map(|cpu| cpu.cpu_usage() as f64).collect::<Vec<_>>());
-    stats.memory_usage = Some(system.used_memory());
-
-    stats.network_usage = Some(
-        system
-            .networks()
-            .into_iter()
-            .map(|(name, data)| NetworkUsageEntry {
-                interface: name.clone(),
-                rx: data.received(),
-                tx: data.transmitted(),
-            })
-            .collect::<Vec<_>>(),
+    stats.memory = procfs::read_proc_file("/proc/meminfo").ok().map(|file| {
+        let mut memory = procfs::parse_meminfo(&file);
+        memory.pressure = procfs::read_proc_file("/proc/pressure/memory")
+            .ok()
+            .and_then(|file| procfs::parse_pressure(&file));
+        memory
+    });
+    stats.memory_usage = Some(
+        stats
+            .memory
+            .as_ref()
+            .map(|memory| memory.used())
+            .unwrap_or_else(|| system.used_memory()),
     );
 
+    measure_rates(&mut stats, counters);
+
+    let mut temperatures = sysfs::read_thermal_zones(Path::new(sysfs::THERMAL_ROOT));
+    temperatures.extend(sysfs::read_hwmon(Path::new(sysfs::HWMON_ROOT)));
+    stats.temperatures = Some(temperatures);
+    stats.cpu_throttle = Some(sysfs::read_cpu_throttle(Path::new(sysfs::CPU_ROOT)));
+
     stats.disk_usage = Some(
         system
             .disks()
@@ -44,6 +179,24 @@ pub fn measure_stats(system: &mut System) -> Stats {
     stats
 }
 
+///the background sampler's latest measurement, so every reader sees the same rates
+pub fn latest_stats() -> Stats {
+    match crate::stream::LIVE_FEED.latest_stats() {
+        Some(entry) => entry.stats.clone(),
+        //only before the first sample, measuring here would refresh the cpu counters under the sampler
+        None => Stats::default(),
+    }
+}
+
+fn process_name(sys_process: &sysinfo::Process) -> String {
+    match sys_process.exe().file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        //kernel threads and processes whose exe we can't read still have a comm
+        None if !sys_process.name().is_empty() => sys_process.name().to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
 pub fn measure_processes(system: &mut System) -> Processes {
     let mut processes: Processes = Default::default();
 
@@ -58,27 +211,274 @@ pub fn measure_processes(system: &mut System) -> Processes {
             continue;
         }
 
-        match sys_process.exe().file_name() {
-            Some(name) => {
-                let process = Process {
-                    name: name.to_string_lossy().to_string(),
-                    cpu_usage: cpu_usage as f64 / cpu_count,
-                    memory_usage,
-                    pid: sys_pid.as_u32(),
-                };
-                processes.push(process);
-            },
-            None => {
-                let process = Process {
-                    name: "unknown".to_string(),
-                    cpu_usage: cpu_usage as f64 / cpu_count,
-                    memory_usage,
-                    pid: sys_pid.as_u32(),
-                };
-                processes.push(process);
+        processes.push(Process {
+            name: process_name(sys_process),
+            cpu_usage: cpu_usage as f64 / cpu_count,
+            memory_usage,
+            pid: sys_pid.as_u32(),
+        });
+    }
+
+    processes
+}
+
+fn sched_policy_name(policy: u32) -> String {
+    match policy {
+        0 => "SCHED_OTHER",
+        1 => "SCHED_FIFO",
+        2 => "SCHED_RR",
+        3 => "SCHED_BATCH",
+        5 => "SCHED_IDLE",
+        6 => "SCHED_DEADLINE",
+        _ => "UNKNOWN",
+    }
+    .to_string()
+}
+
+///reads everything `/proc/<pid>` knows about a single process
+pub fn measure_process_detail(pid: u32) -> Result<ProcessDetail, ShiitakeError> {
+    use nix::unistd::{sysconf, SysconfVar, Uid, User};
+
+    let root = PathBuf::from(format!("/proc/{}", pid));
+    let stat = procfs::parse_pid_stat(&std::fs::read_to_string(root.join("stat"))?)
+        .ok_or(ShiitakeError::DataNotFound)?;
+    let status = procfs::parse_pid_status(&std::fs::read_to_string(root.join("status"))?);
+    let (virtual_pages, resident_pages, shared_pages) =
+        procfs::parse_pid_statm(&std::fs::read_to_string(root.join("statm"))?)
+            .ok_or(ShiitakeError::DataNotFound)?;
+    let boot_time = procfs::read_proc_file("/proc/stat")
+        .map(|file| procfs::parse_stat(&file).boot_time)
+        .unwrap_or(0);
+
+    let page_size = sysconf(SysconfVar::PAGE_SIZE).ok().flatten().unwrap_or(4096) as u64;
+    let clock_ticks = sysconf(SysconfVar::CLK_TCK).ok().flatten().unwrap_or(100) as u64;
+
+    let cmdline = std::fs::read(root.join("cmdline"))
+        .map(|bytes| {
+            bytes
+                .split(|byte| *byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    Ok(ProcessDetail {
+        pid,
+        parent_pid: stat.ppid,
+        exe: std::fs::read_link(root.join("exe"))
+            .ok()
+            .map(|exe| exe.to_string_lossy().to_string()),
+        cmdline,
+        uid: status.uid,
+        user: User::from_uid(Uid::from_raw(status.uid))
+            .ok()
+            .flatten()
+            .map(|user| user.name),
+        state: stat.state.to_string(),
+        start_time: boot_time * 1000 + stat.start_time * 1000 / clock_ticks,
+        threads: stat.num_threads,
+        open_fds: std::fs::read_dir(root.join("fd"))
+            .ok()
+            .map(|fds| fds.count() as u64),
+        sched_policy: sched_policy_name(stat.policy),
+        rt_priority: stat.rt_priority,
+        nice: stat.nice,
+        cpu_affinity: status.cpus_allowed,
+        virtual_memory: virtual_pages * page_size,
+        resident_memory: resident_pages * page_size,
+        shared_memory: shared_pages * page_size,
+        user_cpu_time: stat.utime as f64 / clock_ticks as f64,
+        system_cpu_time: stat.stime as f64 / clock_ticks as f64,
+        name: stat.comm,
+    })
+}
+
+///nests processes under their parents, `entries` pairs each process with its parent pid
+pub fn build_process_tree(entries: Vec<(Process, Option<u32>)>) -> Vec<ProcessTreeNode> {
+    fn build(process: Process, children: &mut HashMap<u32, Vec<Process>>) -> ProcessTreeNode {
+        let mut node = ProcessTreeNode {
+            pid: process.pid,
+            name: process.name,
+            cpu_usage: process.cpu_usage,
+            memory_usage: process.memory_usage,
+            subtree_cpu_usage: process.cpu_usage,
+            subtree_memory_usage: process.memory_usage,
+            children: Vec::new(),
+        };
+        for child in children.remove(&process.pid).unwrap_or_default() {
+            let child = build(child, children);
+            node.subtree_cpu_usage += child.subtree_cpu_usage;
+            node.subtree_memory_usage += child.subtree_memory_usage;
+            node.children.push(child);
+        }
+        node.children.sort_by_key(|child| child.pid);
+        node
+    }
+
+    let pids = entries.iter().map(|(process, _)| process.pid).collect::<std::collections::HashSet<_>>();
+    let mut roots = Vec::new();
+    let mut children: HashMap<u32, Vec<Process>> = HashMap::new();
+    for (process, parent) in entries {
+        match parent {
+            Some(parent) if pids.contains(&parent) && parent != process.pid => {
+                children.entry(parent).or_default().push(process)
             }
+            _ => roots.push(process),
         }
     }
+    let mut tree = roots
+        .into_iter()
+        .map(|root| build(root, &mut children))
+        .collect::<Vec<_>>();
+    tree.sort_by_key(|node| node.pid);
+    tree
+}
 
-    processes
+pub fn measure_process_tree(system: &mut System) -> Vec<ProcessTreeNode> {
+    system.refresh_processes();
+
+    let cpu_count = system.cpus().len() as f64;
+
+    build_process_tree(
+        system
+            .processes()
+            .iter()
+            .map(|(sys_pid, sys_process)| {
+                (
+                    Process {
+                        pid: sys_pid.as_u32(),
+                        name: process_name(sys_process),
+                        cpu_usage: sys_process.cpu_usage() as f64 / cpu_count,
+                        memory_usage: sys_process.memory(),
+                    },
+                    sys_process.parent().map(|parent| parent.as_u32()),
+                )
+            })
+            .collect(),
+    )
+}
+
+///cpu ticks of every thread of a process at the time of the previous thread listing
+struct ThreadSnapshot {
+    taken: Instant,
+    ticks: HashMap<u32, u64>,
+}
+
+#[dynamic]
+#[thread_local]
+static mut PREVIOUS_THREADS: HashMap<u32, ThreadSnapshot> = HashMap::new();
+
+///lists the threads of a process from `/proc/<pid>/task`
+pub fn measure_threads(pid: u32, cpu_count: usize) -> Result<Vec<ThreadInfo>, ShiitakeError> {
+    use nix::unistd::{sysconf, SysconfVar};
+
+    let now = Instant::now();
+    let clock_ticks = sysconf(SysconfVar::CLK_TCK).ok().flatten().unwrap_or(100) as f64;
+    let uptime = procfs::read_proc_file("/proc/uptime")
+        .ok()
+        .and_then(|file| procfs::parse_uptime(&file))
+        .unwrap_or(0.0);
+    let cpu_count = cpu_count.max(1) as f64;
+
+    let mut stats = Vec::new();
+    for task in std::fs::read_dir(format!("/proc/{}/task", pid))? {
+        let task = task?;
+        let Ok(tid) = task.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        //threads can exit between listing the directory and reading them
+        if let Some(stat) = std::fs::read_to_string(task.path().join("stat"))
+            .ok()
+            .and_then(|file| procfs::parse_pid_stat(&file))
+        {
+            stats.push((tid, stat));
+        }
+    }
+
+    let mut previous_threads = PREVIOUS_THREADS.write();
+    //forget processes that have exited so the map doesn't grow forever
+    previous_threads.retain(|pid, _| PathBuf::from(format!("/proc/{}", pid)).exists());
+    let previous = previous_threads.get(&pid);
+    let elapsed = previous
+        .map(|snapshot| now.duration_since(snapshot.taken).as_secs_f64())
+        .unwrap_or(0.0);
+
+    let threads = stats
+        .iter()
+        .map(|(tid, stat)| {
+            let ticks = stat.utime + stat.stime;
+            let cpu_seconds = match previous.and_then(|snapshot| snapshot.ticks.get(tid)) {
+                Some(previous_ticks) if elapsed > 0.0 => {
+                    ticks.saturating_sub(*previous_ticks) as f64 / clock_ticks / elapsed
+                }
+                //no previous sample, fall back to the average over the thread's lifetime
+                _ => {
+                    let lifetime = uptime - stat.start_time as f64 / clock_ticks;
+                    if lifetime > 0.0 {
+                        ticks as f64 / clock_ticks / lifetime
+                    } else {
+                        0.0
+                    }
+                }
+            };
+            ThreadInfo {
+                tid: *tid,
+                name: stat.comm.clone(),
+                state: stat.state.to_string(),
+                cpu_usage: cpu_seconds * 100.0 / cpu_count,
+                sched_policy: sched_policy_name(stat.policy),
+                rt_priority: stat.rt_priority,
+                nice: stat.nice,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    previous_threads.insert(
+        pid,
+        ThreadSnapshot {
+            taken: now,
+            ticks: stats
+                .iter()
+                .map(|(tid, stat)| (*tid, stat.utime + stat.stime))
+                .collect(),
+        },
+    );
+
+    Ok(threads)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn process(pid: u32, cpu_usage: f64, memory_usage: u64) -> Process {
+        Process {
+            pid,
+            name: format!("proc{}", pid),
+            cpu_usage,
+            memory_usage,
+        }
+    }
+
+    #[test]
+    fn test_build_process_tree() {
+        let tree = build_process_tree(vec![
+            (process(1, 0.5, 100), None),
+            (process(20, 1.0, 200), Some(1)),
+            (process(21, 30.0, 5000), Some(20)),
+            (process(22, 2.0, 300), Some(20)),
+            //parent already exited
+            (process(40, 1.0, 10), Some(39)),
+        ]);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].pid, 1);
+        assert_eq!(tree[0].subtree_cpu_usage, 33.5);
+        assert_eq!(tree[0].subtree_memory_usage, 5600);
+        let launcher = &tree[0].children[0];
+        assert_eq!(launcher.pid, 20);
+        assert_eq!(launcher.children.len(), 2);
+        assert_eq!(launcher.subtree_memory_usage, 5500);
+        assert_eq!(tree[1].pid, 40);
+    }
 }
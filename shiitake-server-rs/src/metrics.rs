@@ -0,0 +1,261 @@
+//! Renders everything FarFetched measures in the OpenMetrics text format so Prometheus can scrape it.
+
+use std::fmt::{Display, Write};
+
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use sysinfo::SystemExt;
+
+use crate::types::{NetworkUsageEntry, Process, Stats, Summary};
+
+pub const CONTENT_TYPE_OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+///how many of the busiest processes get their own series
+pub const TOP_PROCESSES: usize = 10;
+
+///name, help text and accessor for one per interface network counter
+type NetworkCounter = (&'static str, &'static str, fn(&NetworkUsageEntry) -> u64);
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+///Appends metric families to a buffer, tagging every sample with the host's identity labels
+struct MetricsWriter {
+    out: String,
+    host_labels: String,
+}
+
+impl MetricsWriter {
+    fn new(summary: &Summary) -> Self {
+        MetricsWriter {
+            out: String::new(),
+            host_labels: format!(
+                "hostname=\"{}\",uuid=\"{:x}\"",
+                escape_label(&summary.hostname),
+                summary.uuid
+            ),
+        }
+    }
+
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = write!(self.out, "{}{{{}", name, self.host_labels);
+        for (key, label) in labels {
+            let _ = write!(self.out, ",{}=\"{}\"", key, escape_label(label));
+        }
+        let _ = writeln!(self.out, "}} {}", value);
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("# EOF\n");
+        self.out
+    }
+}
+
+pub fn render_metrics(summary: &Summary, stats: &Stats, processes: &[Process], uptime: u64) -> String {
+    let mut writer = MetricsWriter::new(summary);
+
+    writer.family("shiitake", "info", "Static information about the host");
+    writer.sample(
+        "shiitake_info",
+        &[("os", &summary.os), ("version", &summary.shiitake_version)],
+        1,
+    );
+
+    writer.family("shiitake_uptime_seconds", "gauge", "Time since the host booted");
+    writer.sample("shiitake_uptime_seconds", &[], uptime);
+
+    if let Some(cpu_usage) = &stats.cpu_usage {
+        writer.family("shiitake_cpu_usage_percent", "gauge", "Usage of each cpu core");
+        for (core, usage) in cpu_usage.iter().enumerate() {
+            writer.sample("shiitake_cpu_usage_percent", &[("cpu", &core.to_string())], usage);
+        }
+    }
+    if let Some(cpu_speed) = &stats.cpu_speed {
+        writer.family("shiitake_cpu_frequency_hertz", "gauge", "Current frequency of each cpu core");
+        for (core, speed) in cpu_speed.iter().enumerate() {
+            writer.sample("shiitake_cpu_frequency_hertz", &[("cpu", &core.to_string())], speed);
+        }
+    }
+
+    if let Some(load) = &stats.load {
+        writer.family("shiitake_load_average", "gauge", "Run queue load averages");
+        for (window, value) in [("1m", load.load_1), ("5m", load.load_5), ("15m", load.load_15)] {
+            writer.sample("shiitake_load_average", &[("window", window)], value);
+        }
+        writer.family("shiitake_procs_running", "gauge", "Tasks currently runnable");
+        writer.sample("shiitake_procs_running", &[], load.running);
+        writer.family("shiitake_context_switches_per_second", "gauge", "Context switches per second");
+        writer.sample("shiitake_context_switches_per_second", &[], load.context_switches_per_sec);
+        writer.family("shiitake_interrupts_per_second", "gauge", "Interrupts per second");
+        writer.sample("shiitake_interrupts_per_second", &[], load.interrupts_per_sec);
+    }
+
+    if let Some(temperatures) = &stats.temperatures {
+        writer.family("shiitake_temperature_celsius", "gauge", "Current reading of each thermal sensor");
+        for entry in temperatures {
+            writer.sample(
+                "shiitake_temperature_celsius",
+                &[("sensor", &entry.source), ("label", &entry.label)],
+                entry.current,
+            );
+        }
+    }
+    if let Some(cpu_throttle) = &stats.cpu_throttle {
+        writer.family("shiitake_cpu_throttled", "gauge", "Whether each cpu core's max frequency is capped");
+        for entry in cpu_throttle {
+            writer.sample(
+                "shiitake_cpu_throttled",
+                &[("cpu", &entry.cpu.to_string())],
+                entry.throttled as u8,
+            );
+        }
+    }
+
+    writer.family("shiitake_memory_total_bytes", "gauge", "Total physical memory");
+    writer.sample("shiitake_memory_total_bytes", &[], summary.total_memory);
+    if let Some(memory_usage) = stats.memory_usage {
+        writer.family("shiitake_memory_used_bytes", "gauge", "Physical memory in use");
+        writer.sample("shiitake_memory_used_bytes", &[], memory_usage);
+    }
+    if let Some(memory) = &stats.memory {
+        let gauges = [
+            ("shiitake_memory_available_bytes", "Memory available without swapping", memory.available),
+            ("shiitake_memory_free_bytes", "Completely unused memory", memory.free),
+            ("shiitake_memory_buffers_bytes", "Memory used by block device buffers", memory.buffers),
+            ("shiitake_memory_cached_bytes", "Memory used by the page cache", memory.cached),
+            ("shiitake_memory_shmem_bytes", "Shared memory and tmpfs", memory.shmem),
+            ("shiitake_memory_slab_bytes", "Kernel slab allocations", memory.slab),
+            ("shiitake_swap_total_bytes", "Total swap space", memory.swap_total),
+            ("shiitake_swap_used_bytes", "Swap space in use", memory.swap_used),
+        ];
+        for (name, help, value) in gauges {
+            writer.family(name, "gauge", help);
+            writer.sample(name, &[], value);
+        }
+    }
+
+    if let Some(network_usage) = &stats.network_usage {
+        let counters: [NetworkCounter; 8] = [
+            ("shiitake_network_receive_bytes", "Bytes received", |entry| entry.total_rx),
+            ("shiitake_network_transmit_bytes", "Bytes transmitted", |entry| entry.total_tx),
+            ("shiitake_network_receive_packets", "Packets received", |entry| entry.total_rx_packets),
+            ("shiitake_network_transmit_packets", "Packets transmitted", |entry| entry.total_tx_packets),
+            ("shiitake_network_receive_errors", "Receive errors", |entry| entry.rx_errors),
+            ("shiitake_network_transmit_errors", "Transmit errors", |entry| entry.tx_errors),
+            ("shiitake_network_receive_dropped", "Received packets dropped", |entry| entry.rx_dropped),
+            ("shiitake_network_transmit_dropped", "Transmitted packets dropped", |entry| entry.tx_dropped),
+        ];
+        for (name, help, value) in counters {
+            writer.family(name, "counter", help);
+            let sample_name = format!("{}_total", name);
+            for entry in network_usage {
+                writer.sample(&sample_name, &[("interface", &entry.interface)], value(entry));
+            }
+        }
+    }
+
+    if let Some(disk_usage) = &stats.disk_usage {
+        writer.family("shiitake_disk_total_bytes", "gauge", "Size of each mounted filesystem");
+        for entry in disk_usage {
+            writer.sample("shiitake_disk_total_bytes", &[("mount_point", &entry.mount_point)], entry.total);
+        }
+        writer.family("shiitake_disk_used_bytes", "gauge", "Space used on each mounted filesystem");
+        for entry in disk_usage {
+            writer.sample("shiitake_disk_used_bytes", &[("mount_point", &entry.mount_point)], entry.used);
+        }
+    }
+
+    if let Some(disk_io) = &stats.disk_io {
+        writer.family("shiitake_disk_read_bytes", "counter", "Bytes read from each block device");
+        for entry in disk_io {
+            writer.sample("shiitake_disk_read_bytes_total", &[("device", &entry.device)], entry.total_read);
+        }
+        writer.family("shiitake_disk_written_bytes", "counter", "Bytes written to each block device");
+        for entry in disk_io {
+            writer.sample("shiitake_disk_written_bytes_total", &[("device", &entry.device)], entry.total_written);
+        }
+    }
+
+    let mut top = processes.iter().collect::<Vec<_>>();
+    top.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage));
+    top.truncate(TOP_PROCESSES);
+    writer.family("shiitake_process_cpu_usage_percent", "gauge", "Cpu usage of the busiest processes");
+    for process in &top {
+        writer.sample(
+            "shiitake_process_cpu_usage_percent",
+            &[("pid", &process.pid.to_string()), ("name", &process.name)],
+            process.cpu_usage,
+        );
+    }
+    writer.family("shiitake_process_resident_memory_bytes", "gauge", "Resident memory of the busiest processes");
+    for process in &top {
+        writer.sample(
+            "shiitake_process_resident_memory_bytes",
+            &[("pid", &process.pid.to_string()), ("name", &process.name)],
+            process.memory_usage,
+        );
+    }
+
+    writer.finish()
+}
+
+pub async fn metrics() -> impl IntoResponse {
+    let stats = crate::measuring::latest_stats();
+    let (processes, uptime) = {
+        let mut system = crate::SYSTEM.write();
+        (crate::measuring::measure_processes(&mut system), system.uptime())
+    };
+    (
+        [(CONTENT_TYPE, CONTENT_TYPE_OPENMETRICS)],
+        render_metrics(&crate::SUMMARY, &stats, &processes, uptime),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let summary = Summary {
+            hostname: "roboRIO-6464-FRC".to_string(),
+            os: "NI Linux".to_string(),
+            shiitake_version: "0.1.1".to_string(),
+            webpage_version: "1.0.0".to_string(),
+            uuid: 0x306addc,
+            cpu_cores: 2,
+            total_memory: 512_000_000,
+            team: Some(6464),
+        };
+        let stats = Stats {
+            cpu_usage: Some(vec![12.5, 50.0]),
+            network_usage: Some(vec![NetworkUsageEntry {
+                interface: "eth0".to_string(),
+                total_rx: 1234,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let processes = vec![Process {
+            pid: 42,
+            name: "java \"robot\"".to_string(),
+            cpu_usage: 30.0,
+            memory_usage: 1000,
+        }];
+
+        let rendered = render_metrics(&summary, &stats, &processes, 60);
+        assert!(rendered.contains("shiitake_cpu_usage_percent{hostname=\"roboRIO-6464-FRC\",uuid=\"306addc\",cpu=\"1\"} 50\n"));
+        assert!(rendered.contains("# TYPE shiitake_network_receive_bytes counter\n"));
+        assert!(rendered.contains("shiitake_network_receive_bytes_total{hostname=\"roboRIO-6464-FRC\",uuid=\"306addc\",interface=\"eth0\"} 1234\n"));
+        assert!(rendered.contains("name=\"java \\\"robot\\\"\"} 30\n"));
+        assert!(rendered.ends_with("# EOF\n"));
+    }
+}
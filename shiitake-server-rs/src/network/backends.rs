@@ -0,0 +1,614 @@
+//! Saving a confirmed interface config in the format of whichever network manager the host boots with, so it survives a reboot.
+//! Every backend reads and writes under a root directory so it can be pointed at a fixture.
+//! The server's settings go in a file of their own named to take priority over the distro's, the distro's files are left alone.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::{prefix_to_mask, InterfaceConfig};
+use crate::backups::BackupStore;
+use crate::ShiitakeResult;
+
+pub trait NetworkBackend {
+    fn name(&self) -> &'static str;
+    ///whether the host's network is set up by this backend
+    fn detect(&self) -> bool;
+    ///the config saved for the interface, `None` if the server hasn't saved one
+    fn read(&self, interface: &str) -> ShiitakeResult<Option<InterfaceConfig>>;
+    fn write(&self, interface: &str, config: &InterfaceConfig) -> ShiitakeResult<()>;
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendChoice {
+    ///the first backend that detects itself, see `backends` for the order
+    Auto,
+    Netplan,
+    NetworkManager,
+    Networkd,
+    Ifupdown,
+    ///don't save network changes, they're lost on reboot
+    None,
+}
+
+///every backend, netplan first since it generates networkd or NetworkManager config
+pub fn backends(files: &BackendFiles) -> Vec<Box<dyn NetworkBackend>> {
+    vec![
+        Box::new(Netplan { files: files.clone() }),
+        Box::new(NetworkManager { files: files.clone() }),
+        Box::new(Networkd { files: files.clone() }),
+        Box::new(Ifupdown { files: files.clone() }),
+    ]
+}
+
+pub fn select(files: &BackendFiles, choice: BackendChoice) -> Option<Box<dyn NetworkBackend>> {
+    let name = match choice {
+        BackendChoice::Auto => return backends(files).into_iter().find(|backend| backend.detect()),
+        BackendChoice::None => return None,
+        BackendChoice::Netplan => Netplan::NAME,
+        BackendChoice::NetworkManager => NetworkManager::NAME,
+        BackendChoice::Networkd => Networkd::NAME,
+        BackendChoice::Ifupdown => Ifupdown::NAME,
+    };
+    backends(files).into_iter().find(|backend| backend.name() == name)
+}
+
+const HEADER: &str = "Written by shiitake, changes made here are overwritten from the web UI";
+
+fn read_optional(path: &Path) -> ShiitakeResult<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+///where a backend's files live and where the files it replaces are backed up to
+#[derive(Clone)]
+pub struct BackendFiles {
+    pub root: PathBuf,
+    pub backups: BackupStore,
+}
+
+impl BackendFiles {
+    fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    fn write(&self, path: &Path, contents: &str, mode: u32) -> ShiitakeResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.backups.write(path, contents.as_bytes())?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        Ok(())
+    }
+}
+
+fn join(servers: &[IpAddr], separator: &str) -> String {
+    servers.iter().map(|server| server.to_string()).collect::<Vec<_>>().join(separator)
+}
+
+fn mask_to_prefix(mask: Ipv4Addr) -> u8 {
+    u32::from(mask).count_ones() as u8
+}
+
+fn parse_cidr(cidr: &str) -> Option<(Ipv4Addr, u8)> {
+    let (address, prefix) = cidr.trim().split_once('/')?;
+    Some((address.parse().ok()?, prefix.parse().ok()?))
+}
+
+///`key=value` lines, ignoring section headers and comments
+fn key_values(file: &str) -> impl Iterator<Item = (&str, &str)> {
+    file.lines()
+        .filter(|line| !line.trim_start().starts_with(['#', ';', '[']))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+}
+
+///systemd-networkd `.network` files in /etc/systemd/network
+pub struct Networkd {
+    pub files: BackendFiles,
+}
+
+impl Networkd {
+    const NAME: &'static str = "systemd-networkd";
+
+    ///networkd uses the first matching file in lexical order
+    fn path(&self, interface: &str) -> PathBuf {
+        self.files.path(&format!("etc/systemd/network/10-shiitake-{}.network", interface))
+    }
+
+    pub fn render(interface: &str, config: &InterfaceConfig) -> String {
+        let mut out = format!("# {}\n[Match]\nName={}\n\n[Network]\n", HEADER, interface);
+        match config {
+            InterfaceConfig::Dhcp => out.push_str("DHCP=ipv4\n"),
+            InterfaceConfig::Static { address, prefix, gateway, dns } => {
+                out.push_str(&format!("Address={}/{}\n", address, prefix));
+                if let Some(gateway) = gateway {
+                    out.push_str(&format!("Gateway={}\n", gateway));
+                }
+                for server in dns {
+                    out.push_str(&format!("DNS={}\n", server));
+                }
+            }
+            InterfaceConfig::LinkLocal => out.push_str("DHCP=no\nLinkLocalAddressing=ipv4\n"),
+        }
+        out
+    }
+
+    pub fn parse(file: &str) -> Option<InterfaceConfig> {
+        let mut address = None;
+        let mut gateway = None;
+        let mut dns = Vec::new();
+        let mut mode = None;
+        for (key, value) in key_values(file) {
+            match key {
+                "DHCP" if ["yes", "ipv4", "true"].contains(&value) => mode = Some(InterfaceConfig::Dhcp),
+                "LinkLocalAddressing" if ["yes", "ipv4"].contains(&value) => mode = Some(InterfaceConfig::LinkLocal),
+                "Address" => address = address.or(parse_cidr(value)),
+                "Gateway" => gateway = value.parse().ok(),
+                "DNS" => dns.extend(value.split_whitespace().filter_map(|server| server.parse::<IpAddr>().ok())),
+                _ => {}
+            }
+        }
+        match (mode, address) {
+            (Some(InterfaceConfig::Dhcp), _) => Some(InterfaceConfig::Dhcp),
+            (_, Some((address, prefix))) => Some(InterfaceConfig::Static { address, prefix, gateway, dns }),
+            (mode, None) => mode,
+        }
+    }
+}
+
+impl NetworkBackend for Networkd {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn detect(&self) -> bool {
+        //the unit is enabled with a symlink, which doesn't need to resolve under a fixture root
+        self.files.path("etc/systemd/system/multi-user.target.wants/systemd-networkd.service")
+            .symlink_metadata()
+            .is_ok()
+    }
+
+    fn read(&self, interface: &str) -> ShiitakeResult<Option<InterfaceConfig>> {
+        Ok(read_optional(&self.path(interface))?.as_deref().and_then(Self::parse))
+    }
+
+    fn write(&self, interface: &str, config: &InterfaceConfig) -> ShiitakeResult<()> {
+        self.files.write(&self.path(interface), &Self::render(interface, config), 0o644)
+    }
+}
+
+///NetworkManager keyfiles in /etc/NetworkManager/system-connections
+pub struct NetworkManager {
+    pub files: BackendFiles,
+}
+
+impl NetworkManager {
+    const NAME: &'static str = "NetworkManager";
+
+    fn path(&self, interface: &str) -> PathBuf {
+        self.files.path(&format!("etc/NetworkManager/system-connections/shiitake-{}.nmconnection", interface))
+    }
+
+    pub fn render(interface: &str, config: &InterfaceConfig) -> String {
+        //a higher priority than the distro's profiles for the same device
+        let mut out = format!(
+            "# {}\n[connection]\nid=shiitake-{}\ntype=ethernet\ninterface-name={}\nautoconnect-priority=100\n\n[ipv4]\n",
+            HEADER, interface, interface
+        );
+        match config {
+            InterfaceConfig::Dhcp => out.push_str("method=auto\n"),
+            InterfaceConfig::Static { address, prefix, gateway, dns } => {
+                out.push_str("method=manual\n");
+                match gateway {
+                    Some(gateway) => out.push_str(&format!("address1={}/{},{}\n", address, prefix, gateway)),
+                    None => out.push_str(&format!("address1={}/{}\n", address, prefix)),
+                }
+                let (v4, v6): (Vec<IpAddr>, Vec<IpAddr>) = dns.iter().partition(|server| server.is_ipv4());
+                if !v4.is_empty() {
+                    out.push_str(&format!("dns={};\n", join(&v4, ";")));
+                }
+                if !v6.is_empty() {
+                    out.push_str(&format!("\n[ipv6]\nmethod=auto\ndns={};\n", join(&v6, ";")));
+                }
+            }
+            InterfaceConfig::LinkLocal => out.push_str("method=link-local\n"),
+        }
+        out
+    }
+
+    pub fn parse(file: &str) -> Option<InterfaceConfig> {
+        let mut method = None;
+        let mut address = None;
+        let mut gateway = None;
+        let mut dns = Vec::new();
+        for (key, value) in key_values(file) {
+            match key {
+                //the [ipv6] section only ever has auto
+                "method" if method.is_none() => method = Some(value),
+                "address1" => {
+                    let (cidr, via) = value.split_once(',').unwrap_or((value, ""));
+                    address = parse_cidr(cidr);
+                    gateway = via.parse().ok();
+                }
+                "dns" => dns.extend(value.split(';').filter_map(|server| server.trim().parse::<IpAddr>().ok())),
+                _ => {}
+            }
+        }
+        match method? {
+            "auto" => Some(InterfaceConfig::Dhcp),
+            "link-local" => Some(InterfaceConfig::LinkLocal),
+            "manual" => address.map(|(address, prefix)| InterfaceConfig::Static { address, prefix, gateway, dns }),
+            _ => None,
+        }
+    }
+}
+
+impl NetworkBackend for NetworkManager {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn detect(&self) -> bool {
+        self.files.path("etc/NetworkManager/system-connections").is_dir()
+    }
+
+    fn read(&self, interface: &str) -> ShiitakeResult<Option<InterfaceConfig>> {
+        Ok(read_optional(&self.path(interface))?.as_deref().and_then(Self::parse))
+    }
+
+    fn write(&self, interface: &str, config: &InterfaceConfig) -> ShiitakeResult<()> {
+        //NetworkManager ignores keyfiles anyone else can read
+        self.files.write(&self.path(interface), &Self::render(interface, config), 0o600)
+    }
+}
+
+///Debian's /etc/network/interfaces.
+///There's no drop-in that reliably wins here, so the interface's `inet` stanza is swapped in place.
+pub struct Ifupdown {
+    pub files: BackendFiles,
+}
+
+const STANZA_KEYWORDS: &[&str] = &["iface", "auto", "mapping", "source", "source-directory", "rename"];
+
+fn starts_stanza(line: &str) -> bool {
+    let keyword = line.split_whitespace().next().unwrap_or("");
+    STANZA_KEYWORDS.contains(&keyword) || keyword.starts_with("allow-")
+}
+
+fn is_inet_stanza(line: &str, interface: &str) -> bool {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    words.len() >= 3 && words[0] == "iface" && words[1] == interface && words[2] == "inet"
+}
+
+impl Ifupdown {
+    const NAME: &'static str = "ifupdown";
+
+    fn path(&self) -> PathBuf {
+        self.files.path("etc/network/interfaces")
+    }
+
+    fn stanza(interface: &str, config: &InterfaceConfig) -> String {
+        match config {
+            InterfaceConfig::Dhcp => format!("iface {} inet dhcp\n", interface),
+            InterfaceConfig::Static { address, prefix, gateway, dns } => {
+                //netmask instead of a /prefix on the address for older ifupdown
+                let mut out = format!(
+                    "iface {} inet static\n    address {}\n    netmask {}\n",
+                    interface,
+                    address,
+                    prefix_to_mask(*prefix)
+                );
+                if let Some(gateway) = gateway {
+                    out.push_str(&format!("    gateway {}\n", gateway));
+                }
+                if !dns.is_empty() {
+                    out.push_str(&format!("    dns-nameservers {}\n", join(dns, " ")));
+                }
+                out
+            }
+            InterfaceConfig::LinkLocal => format!("iface {} inet ipv4ll\n", interface),
+        }
+    }
+
+    ///`file` with the interface's `inet` stanza replaced, everything else including its `inet6` stanza is kept
+    pub fn render(file: &str, interface: &str, config: &InterfaceConfig) -> String {
+        let mut out = String::new();
+        let mut skipping = false;
+        for line in file.lines().filter(|line| line.trim() != format!("# {}", HEADER)) {
+            if starts_stanza(line) {
+                skipping = is_inet_stanza(line, interface);
+            }
+            if !skipping {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        let brought_up = file.lines().any(|line| {
+            let mut words = line.split_whitespace();
+            words.next().is_some_and(|keyword| keyword == "auto" || keyword.starts_with("allow-"))
+                && words.any(|word| word == interface)
+        });
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        out.push_str(&format!("# {}\n", HEADER));
+        if !brought_up {
+            out.push_str(&format!("auto {}\n", interface));
+        }
+        out.push_str(&Self::stanza(interface, config));
+        out
+    }
+
+    pub fn parse(file: &str, interface: &str) -> Option<InterfaceConfig> {
+        let mut lines = file.lines().skip_while(|line| !is_inet_stanza(line, interface));
+        let method = lines.next()?.split_whitespace().nth(3)?.to_string();
+        let mut address = None;
+        let mut prefix = None;
+        let mut gateway = None;
+        let mut dns = Vec::new();
+        for line in lines.take_while(|line| !starts_stanza(line)) {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("address"), Some(value)) => match parse_cidr(value) {
+                    Some((ip, cidr_prefix)) => {
+                        address = Some(ip);
+                        prefix = Some(cidr_prefix);
+                    }
+                    None => address = value.parse().ok(),
+                },
+                (Some("netmask"), Some(value)) => prefix = value.parse().ok().map(mask_to_prefix),
+                (Some("gateway"), Some(value)) => gateway = value.parse().ok(),
+                (Some("dns-nameservers"), Some(first)) => {
+                    dns.extend(std::iter::once(first).chain(words).filter_map(|server| server.parse::<IpAddr>().ok()))
+                }
+                _ => {}
+            }
+        }
+        match method.as_str() {
+            "dhcp" => Some(InterfaceConfig::Dhcp),
+            "ipv4ll" => Some(InterfaceConfig::LinkLocal),
+            "static" => Some(InterfaceConfig::Static {
+                address: address?,
+                prefix: prefix?,
+                gateway,
+                dns,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl NetworkBackend for Ifupdown {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn detect(&self) -> bool {
+        self.path().is_file()
+    }
+
+    fn read(&self, interface: &str) -> ShiitakeResult<Option<InterfaceConfig>> {
+        Ok(read_optional(&self.path())?.and_then(|file| Self::parse(&file, interface)))
+    }
+
+    fn write(&self, interface: &str, config: &InterfaceConfig) -> ShiitakeResult<()> {
+        let file = read_optional(&self.path())?.unwrap_or_default();
+        self.files.write(&self.path(), &Self::render(&file, interface, config), 0o644)
+    }
+}
+
+///netplan YAML in /etc/netplan
+pub struct Netplan {
+    pub files: BackendFiles,
+}
+
+impl Netplan {
+    const NAME: &'static str = "netplan";
+
+    ///netplan merges its files in lexical order, later files win
+    fn path(&self, interface: &str) -> PathBuf {
+        self.files.path(&format!("etc/netplan/90-shiitake-{}.yaml", interface))
+    }
+
+    pub fn render(interface: &str, config: &InterfaceConfig) -> String {
+        let mut out = format!("# {}\nnetwork:\n  version: 2\n  ethernets:\n    {}:\n", HEADER, interface);
+        match config {
+            InterfaceConfig::Dhcp => out.push_str("      dhcp4: true\n"),
+            InterfaceConfig::Static { address, prefix, gateway, dns } => {
+                out.push_str("      dhcp4: false\n");
+                out.push_str(&format!("      addresses: [{}/{}]\n", address, prefix));
+                if let Some(gateway) = gateway {
+                    out.push_str(&format!("      routes:\n        - to: default\n          via: {}\n", gateway));
+                }
+                if !dns.is_empty() {
+                    out.push_str(&format!(
+                        "      nameservers:\n        addresses: [{}]\n",
+                        join(dns, ", ")
+                    ));
+                }
+            }
+            InterfaceConfig::LinkLocal => out.push_str("      dhcp4: false\n      link-local: [ipv4]\n"),
+        }
+        out
+    }
+
+    ///only understands the layout `render` writes
+    pub fn parse(file: &str) -> Option<InterfaceConfig> {
+        let mut dhcp = false;
+        let mut link_local = false;
+        let mut address = None;
+        let mut gateway = None;
+        let mut dns = Vec::new();
+        let mut in_nameservers = false;
+        for line in file.lines() {
+            let line = line.trim().trim_start_matches("- ");
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let items = value
+                .trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .collect::<Vec<_>>();
+            match key {
+                "dhcp4" => dhcp = value.trim() == "true",
+                "link-local" => link_local = items.contains(&"ipv4"),
+                "nameservers" => in_nameservers = true,
+                "addresses" if in_nameservers => dns.extend(items.iter().filter_map(|item| item.parse::<IpAddr>().ok())),
+                "addresses" => address = items.first().and_then(|item| parse_cidr(item)),
+                "via" => gateway = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        if dhcp {
+            return Some(InterfaceConfig::Dhcp);
+        }
+        match address {
+            Some((address, prefix)) => Some(InterfaceConfig::Static { address, prefix, gateway, dns }),
+            None if link_local => Some(InterfaceConfig::LinkLocal),
+            None => None,
+        }
+    }
+}
+
+impl NetworkBackend for Netplan {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn detect(&self) -> bool {
+        std::fs::read_dir(self.files.path("etc/netplan")).is_ok_and(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .any(|entry| entry.path().extension().is_some_and(|extension| extension == "yaml"))
+        })
+    }
+
+    fn read(&self, interface: &str) -> ShiitakeResult<Option<InterfaceConfig>> {
+        Ok(read_optional(&self.path(interface))?.as_deref().and_then(Self::parse))
+    }
+
+    fn write(&self, interface: &str, config: &InterfaceConfig) -> ShiitakeResult<()> {
+        //netplan warns about world readable files
+        self.files.write(&self.path(interface), &Self::render(interface, config), 0o600)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/network").join(name)
+    }
+
+    fn files(root: &Path) -> BackendFiles {
+        BackendFiles {
+            root: root.to_path_buf(),
+            backups: BackupStore {
+                dir: root.join("var/lib/shiitake/backups"),
+                keep: 2,
+            },
+        }
+    }
+
+    fn copy_dir(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap().map(Result::unwrap) {
+            let target = to.join(entry.file_name());
+            let file_type = entry.file_type().unwrap();
+            if file_type.is_dir() {
+                copy_dir(&entry.path(), &target);
+            } else if file_type.is_symlink() {
+                std::os::unix::fs::symlink(std::fs::read_link(entry.path()).unwrap(), target).unwrap();
+            } else {
+                std::fs::copy(entry.path(), target).unwrap();
+            }
+        }
+    }
+
+    ///a writable copy of a fixture
+    fn scratch(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("network-{}", name));
+        copy_dir(&fixture(name), &dir);
+        dir
+    }
+
+    fn configs() -> Vec<InterfaceConfig> {
+        vec![
+            InterfaceConfig::Dhcp,
+            InterfaceConfig::LinkLocal,
+            InterfaceConfig::Static {
+                address: Ipv4Addr::new(10, 64, 64, 11),
+                prefix: 24,
+                gateway: Some(Ipv4Addr::new(10, 64, 64, 1)),
+                dns: vec!["10.64.64.1".parse().unwrap(), "2606:4700::1111".parse().unwrap()],
+            },
+            InterfaceConfig::Static {
+                address: Ipv4Addr::new(10, 64, 64, 12),
+                prefix: 8,
+                gateway: None,
+                dns: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_detect() {
+        for (name, expected) in [
+            ("netplan", "netplan"),
+            ("network_manager", "NetworkManager"),
+            ("networkd", "systemd-networkd"),
+            ("ifupdown", "ifupdown"),
+        ] {
+            let backend = select(&files(&fixture(name)), BackendChoice::Auto).unwrap();
+            assert_eq!(backend.name(), expected);
+        }
+        assert!(select(&files(&fixture("ifupdown")), BackendChoice::None).is_none());
+        assert_eq!(select(&files(&fixture("ifupdown")), BackendChoice::Netplan).unwrap().name(), "netplan");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for name in ["netplan", "network_manager", "networkd", "ifupdown"] {
+            let root = scratch(name);
+            let files = files(&root);
+            let backend = select(&files, BackendChoice::Auto).unwrap();
+            for config in configs() {
+                backend.write("eth0", &config).unwrap();
+                assert_eq!(backend.read("eth0").unwrap(), Some(config), "{}", name);
+            }
+            assert_eq!(files.backups.list().unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_ifupdown_keeps_other_stanzas() {
+        let file = std::fs::read_to_string(fixture("ifupdown").join("etc/network/interfaces")).unwrap();
+        assert_eq!(Ifupdown::parse(&file, "eth0"), Some(InterfaceConfig::Dhcp));
+        let config = InterfaceConfig::Static {
+            address: Ipv4Addr::new(10, 64, 64, 11),
+            prefix: 24,
+            gateway: None,
+            dns: Vec::new(),
+        };
+        let rendered = Ifupdown::render(&file, "eth0", &config);
+        assert!(rendered.contains("iface lo inet loopback"));
+        assert!(rendered.contains("iface eth0 inet6 auto"));
+        assert!(!rendered.contains("inet dhcp"));
+        assert!(!rendered.contains("auto eth0"));
+        assert!(rendered.ends_with("iface eth0 inet static\n    address 10.64.64.11\n    netmask 255.255.255.0\n"));
+        assert_eq!(Ifupdown::parse(&rendered, "eth0"), Some(config));
+    }
+}
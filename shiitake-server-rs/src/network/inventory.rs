@@ -0,0 +1,233 @@
+//! Everything about the host's interfaces, routes and resolver in one response,
+//! for working out why a link is down without a shell on the robot.
+
+use std::net::IpAddr;
+use std::path::Path;
+
+use axum::Json;
+use serde::Serialize;
+
+use super::{dhcp_client_pids, ip, parse_nameservers, resolv_conf_path};
+use crate::sysfs::read_net_links;
+use crate::types::NetLinkEntry;
+use crate::ShiitakeResult;
+
+///words `ip route` puts before the destination of routes that aren't unicast
+const ROUTE_TYPES: &[&str] = &[
+    "unicast",
+    "local",
+    "broadcast",
+    "multicast",
+    "throw",
+    "unreachable",
+    "prohibit",
+    "blackhole",
+    "nat",
+    "anycast",
+];
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InterfaceAddress {
+    pub address: IpAddr,
+    pub prefix: u8,
+    ///`global`, `link`, `host`...
+    pub scope: Option<String>,
+    ///handed out by dhcp or slaac and will expire
+    pub dynamic: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Route {
+    ///a network in CIDR notation or `default`
+    pub destination: String,
+    pub gateway: Option<IpAddr>,
+    pub interface: Option<String>,
+    ///who added the route, `kernel`, `dhcp`, `static`...
+    pub protocol: Option<String>,
+    pub metric: Option<u32>,
+    ///the address used for traffic the host sends over the route
+    pub source: Option<IpAddr>,
+}
+
+#[derive(Debug, Serialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Resolver {
+    pub nameservers: Vec<IpAddr>,
+    pub search: Vec<String>,
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterfaceInfo {
+    #[serde(flatten)]
+    pub link: NetLinkEntry,
+    pub addresses: Vec<InterfaceAddress>,
+    ///has a dynamic IPv4 address or a dhcp client running for it
+    pub dhcp: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkInventory {
+    pub interfaces: Vec<InterfaceInfo>,
+    pub routes: Vec<Route>,
+    pub resolver: Resolver,
+}
+
+///the addresses in `ip -o addr show` output with the interface they're on
+pub fn parse_all_addresses(output: &str) -> Vec<(String, InterfaceAddress)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            //`2: eth0    inet 10.0.0.2/24 brd 10.0.0.255 scope global dynamic eth0\ valid_lft ...`
+            let mut words = line.split_whitespace().map(|word| word.trim_end_matches('\\'));
+            let interface = words.nth(1)?;
+            let interface = interface.split_once('@').map_or(interface, |(name, _)| name);
+            if !matches!(words.next()?, "inet" | "inet6") {
+                return None;
+            }
+            let (address, prefix) = words.next()?.split_once('/')?;
+            let rest = words.collect::<Vec<_>>();
+            Some((
+                interface.to_string(),
+                InterfaceAddress {
+                    address: address.parse().ok()?,
+                    prefix: prefix.parse().ok()?,
+                    scope: rest
+                        .iter()
+                        .position(|word| *word == "scope")
+                        .and_then(|i| rest.get(i + 1))
+                        .map(|scope| scope.to_string()),
+                    dynamic: rest.contains(&"dynamic"),
+                },
+            ))
+        })
+        .collect()
+}
+
+///the routes in `ip -o route show` output
+pub fn parse_routes(output: &str) -> Vec<Route> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace().peekable();
+            if words.peek().is_some_and(|word| ROUTE_TYPES.contains(word)) {
+                words.next();
+            }
+            let mut route = Route {
+                destination: words.next()?.to_string(),
+                gateway: None,
+                interface: None,
+                protocol: None,
+                metric: None,
+                source: None,
+            };
+            while let Some(word) = words.next() {
+                match word {
+                    "via" => route.gateway = words.next().and_then(|gateway| gateway.parse().ok()),
+                    "dev" => route.interface = words.next().map(String::from),
+                    "proto" => route.protocol = words.next().map(String::from),
+                    "metric" => route.metric = words.next().and_then(|metric| metric.parse().ok()),
+                    "src" => route.source = words.next().and_then(|source| source.parse().ok()),
+                    _ => {}
+                }
+            }
+            Some(route)
+        })
+        .collect()
+}
+
+pub fn parse_resolver(resolv_conf: &str) -> Resolver {
+    let mut resolver = Resolver {
+        nameservers: parse_nameservers(resolv_conf),
+        ..Default::default()
+    };
+    for line in resolv_conf.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            //the last of `search` and `domain` wins
+            Some("search") | Some("domain") => resolver.search = words.map(String::from).collect(),
+            Some("options") => resolver.options.extend(words.map(String::from)),
+            _ => {}
+        }
+    }
+    resolver
+}
+
+pub async fn get_inventory() -> ShiitakeResult<Json<NetworkInventory>> {
+    let addresses = parse_all_addresses(&ip(&["-o", "addr", "show"])?);
+    let mut routes = parse_routes(&ip(&["-o", "-4", "route", "show"])?);
+    //hosts with IPv6 turned off have no IPv6 routing table to show
+    routes.extend(parse_routes(&ip(&["-o", "-6", "route", "show"]).unwrap_or_default()));
+    let interfaces = read_net_links(Path::new(crate::sysfs::NET_ROOT))
+        .into_iter()
+        .map(|link| {
+            let addresses = addresses
+                .iter()
+                .filter(|(interface, _)| *interface == link.interface)
+                .map(|(_, address)| address.clone())
+                .collect::<Vec<_>>();
+            let dynamic = addresses.iter().any(|address| address.address.is_ipv4() && address.dynamic);
+            InterfaceInfo {
+                dhcp: dynamic || !dhcp_client_pids(&link.interface).is_empty(),
+                link,
+                addresses,
+            }
+        })
+        .collect();
+    Ok(Json(NetworkInventory {
+        interfaces,
+        routes,
+        resolver: parse_resolver(&std::fs::read_to_string(resolv_conf_path()).unwrap_or_default()),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_addresses() {
+        let output = "1: lo    inet 127.0.0.1/8 scope host lo\\       valid_lft forever preferred_lft forever\n\
+                      2: eth0    inet 10.64.64.2/24 brd 10.64.64.255 scope global dynamic eth0\\       valid_lft 86000sec preferred_lft 86000sec\n\
+                      2: eth0    inet6 fe80::280:2fff:fe25:7e70/64 scope link \\       valid_lft forever preferred_lft forever\n\
+                      5: veth0@if4    inet6 fd00::2/64 scope global nodad \\       valid_lft forever preferred_lft forever\n";
+        let addresses = parse_all_addresses(output);
+        assert_eq!(addresses.len(), 4);
+        assert_eq!(addresses[0].0, "lo");
+        assert_eq!(addresses[0].1.scope.as_deref(), Some("host"));
+        assert_eq!(addresses[1].1.address, "10.64.64.2".parse::<IpAddr>().unwrap());
+        assert!(addresses[1].1.dynamic);
+        assert_eq!(addresses[2].1.prefix, 64);
+        assert!(!addresses[2].1.dynamic);
+        assert_eq!(addresses[3].0, "veth0");
+    }
+
+    #[test]
+    fn test_parse_routes() {
+        let output = "default via 10.64.64.1 dev eth0 proto dhcp src 10.64.64.2 metric 100\n\
+                      10.64.64.0/24 dev eth0 proto kernel scope link src 10.64.64.2\n\
+                      unreachable ::/96 dev lo metric 1024 pref medium\n";
+        let routes = parse_routes(output);
+        assert_eq!(routes.len(), 3);
+        assert_eq!(routes[0].destination, "default");
+        assert_eq!(routes[0].gateway, Some("10.64.64.1".parse().unwrap()));
+        assert_eq!(routes[0].protocol.as_deref(), Some("dhcp"));
+        assert_eq!(routes[0].metric, Some(100));
+        assert_eq!(routes[1].gateway, None);
+        assert_eq!(routes[1].source, Some("10.64.64.2".parse().unwrap()));
+        assert_eq!(routes[2].destination, "::/96");
+        assert_eq!(routes[2].interface.as_deref(), Some("lo"));
+    }
+
+    #[test]
+    fn test_parse_resolver() {
+        let resolver = parse_resolver("# generated\nnameserver 10.64.64.1\nsearch lan frc\noptions edns0\noptions timeout:2\n");
+        assert_eq!(resolver.nameservers, vec!["10.64.64.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(resolver.search, vec!["lan", "frc"]);
+        assert_eq!(resolver.options, vec!["edns0", "timeout:2"]);
+    }
+}
@@ -0,0 +1,767 @@
+//! Changing how an interface is addressed at runtime.
+//! Every change is reverted automatically unless the client confirms it within a timeout,
+//! so a typo in an address can't lock everyone out of the robot.
+
+pub mod backends;
+pub mod inventory;
+pub mod neighbors;
+pub mod team;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::Path as UrlPath,
+    routing::{get, post},
+    Json, Router,
+};
+use nix::{sys::signal::Signal, unistd::Pid};
+use serde::{Deserialize, Serialize};
+use static_init::dynamic;
+
+use self::backends::{BackendChoice, BackendFiles, NetworkBackend};
+use crate::backups::BackupStore;
+use crate::types::routes;
+use crate::{ShiitakeError, ShiitakeResult};
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::error!($($arg)*);
+    }
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::warn!($($arg)*);
+    }
+}
+
+pub const DEFAULT_CONFIRM_TIMEOUT: u64 = 30;
+pub const MIN_CONFIRM_TIMEOUT: u64 = 5;
+pub const MAX_CONFIRM_TIMEOUT: u64 = 600;
+///long enough for the response to reach the client before its address goes away
+const APPLY_DELAY: Duration = Duration::from_millis(500);
+///glibc only reads the first three nameservers
+const MAX_DNS_SERVERS: usize = 3;
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+///dhcp clients the server knows how to start, with the arguments that put them in the background for an interface
+const DHCP_CLIENTS: &[(&str, &[&str])] = &[("dhclient", &["-nw"]), ("udhcpc", &["-b", "-i"]), ("dhcpcd", &["-b"])];
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    ///prefix for the network manager's config files, point it at a copy to run off-target
+    pub root: PathBuf,
+    ///where confirmed changes are saved
+    pub backend: BackendChoice,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            root: PathBuf::from("/"),
+            backend: BackendChoice::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum InterfaceConfig {
+    Dhcp,
+    Static {
+        address: Ipv4Addr,
+        prefix: u8,
+        #[serde(default)]
+        gateway: Option<Ipv4Addr>,
+        #[serde(default)]
+        dns: Vec<IpAddr>,
+    },
+    ///a 169.254.0.0/16 address picked from the interface's MAC, what the Rio falls back to without a DHCP server
+    LinkLocal,
+}
+
+pub fn prefix_to_mask(prefix: u8) -> Ipv4Addr {
+    Ipv4Addr::from(u32::MAX.checked_shl(32 - prefix.min(32) as u32).unwrap_or(0))
+}
+
+fn network_of(address: Ipv4Addr, prefix: u8) -> u32 {
+    u32::from(address) & u32::from(prefix_to_mask(prefix))
+}
+
+impl InterfaceConfig {
+    pub fn validate(&self) -> ShiitakeResult<()> {
+        let InterfaceConfig::Static { address, prefix, gateway, dns } = self else {
+            return Ok(());
+        };
+        let invalid = |reason: String| Err(ShiitakeError::BadRequest(reason));
+        if !(1..=32).contains(prefix) {
+            return invalid("prefix must be between 1 and 32".to_string());
+        }
+        let usable = |ip: &Ipv4Addr| {
+            let host = u32::from(*ip) & !u32::from(prefix_to_mask(*prefix));
+            //the network and broadcast addresses only exist in subnets with room for them
+            let reserved = *prefix <= 30 && (host == 0 || host == !u32::from(prefix_to_mask(*prefix)));
+            !(ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() || ip.is_broadcast() || reserved)
+        };
+        if !usable(address) || address.is_link_local() {
+            return invalid(format!("{}/{} can't be assigned to an interface", address, prefix));
+        }
+        if let Some(gateway) = gateway {
+            if gateway == address || !usable(gateway) || network_of(*gateway, *prefix) != network_of(*address, *prefix) {
+                return invalid(format!("Gateway {} isn't another host in {}/{}", gateway, address, prefix));
+            }
+        }
+        if dns.len() > MAX_DNS_SERVERS {
+            return invalid(format!("At most {} dns servers are supported", MAX_DNS_SERVERS));
+        }
+        if let Some(server) = dns.iter().find(|server| server.is_unspecified() || server.is_multicast()) {
+            return invalid(format!("{} isn't a usable dns server", server));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Address {
+    pub address: Ipv4Addr,
+    pub prefix: u8,
+    ///handed out by a dhcp server
+    pub dynamic: bool,
+}
+
+///the addresses in `ip -o -4 addr show` output
+pub fn parse_addresses(output: &str) -> Vec<Address> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            words.find(|word| *word == "inet")?;
+            let (address, prefix) = words.next()?.split_once('/')?;
+            Some(Address {
+                address: address.parse().ok()?,
+                prefix: prefix.parse().ok()?,
+                dynamic: line.split_whitespace().any(|word| word == "dynamic"),
+            })
+        })
+        .collect()
+}
+
+///the gateway in `ip -4 route show default` output
+pub fn parse_default_gateway(output: &str) -> Option<Ipv4Addr> {
+    output.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        words.find(|word| *word == "via")?;
+        words.next()?.parse().ok()
+    })
+}
+
+pub fn parse_nameservers(resolv_conf: &str) -> Vec<IpAddr> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|server| server.trim().parse().ok())
+        .collect()
+}
+
+///swaps the nameservers in resolv.conf for `dns`, keeping search domains and options
+pub fn render_resolv_conf(current: &str, dns: &[IpAddr]) -> String {
+    let mut out = current
+        .lines()
+        .filter(|line| !line.trim().starts_with("nameserver"))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    for server in dns {
+        out.push_str(&format!("nameserver {}\n", server));
+    }
+    out
+}
+
+///169.254.1.0-169.254.254.255 as RFC 3927 asks, picked from the end of the MAC so it stays the same across boots
+pub fn link_local_address(mac: &str) -> Option<Ipv4Addr> {
+    let bytes = mac
+        .trim()
+        .split(':')
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let [.., a, b] = bytes[..] else {
+        return None;
+    };
+    Some(Ipv4Addr::new(169, 254, a % 254 + 1, b))
+}
+
+///the mode the addresses look like they were set up with
+pub fn current_config(addresses: &[Address], gateway: Option<Ipv4Addr>, dns: Vec<IpAddr>) -> Option<InterfaceConfig> {
+    if addresses.iter().any(|address| address.dynamic) {
+        return Some(InterfaceConfig::Dhcp);
+    }
+    match addresses.iter().find(|address| !address.address.is_link_local()) {
+        Some(address) => Some(InterfaceConfig::Static {
+            address: address.address,
+            prefix: address.prefix,
+            gateway,
+            dns,
+        }),
+        None if !addresses.is_empty() => Some(InterfaceConfig::LinkLocal),
+        None => None,
+    }
+}
+
+pub fn ip(args: &[&str]) -> ShiitakeResult<String> {
+    let output = Command::new("ip").args(args).output()?;
+    if !output.status.success() {
+        return Err(ShiitakeError::CommandFailed(format!(
+            "ip {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn check_interface(interface: &str) -> ShiitakeResult<()> {
+    let valid_name = (1..=15).contains(&interface.len())
+        && interface.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        && !interface.starts_with('.');
+    if !valid_name || !Path::new(crate::sysfs::NET_ROOT).join(interface).exists() {
+        return Err(ShiitakeError::NotFound(format!("No interface {}", interface)));
+    }
+    if interface == "lo" {
+        return Err(ShiitakeError::Forbidden("The loopback interface can't be reconfigured".to_string()));
+    }
+    Ok(())
+}
+
+pub fn resolv_conf_path() -> PathBuf {
+    //write through a symlink to e.g. systemd-resolved's file instead of replacing the link
+    std::fs::canonicalize(RESOLV_CONF).unwrap_or_else(|_| PathBuf::from(RESOLV_CONF))
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    std::env::var("PATH")
+        .unwrap_or_default()
+        .split(':')
+        .chain(["/sbin", "/usr/sbin"])
+        .map(|dir| Path::new(dir).join(name))
+        .find(|path| path.is_file())
+}
+
+fn start_dhcp(interface: &str) -> ShiitakeResult<()> {
+    let (program, args) = DHCP_CLIENTS
+        .iter()
+        .find_map(|(name, args)| Some((find_program(name)?, args)))
+        .ok_or_else(|| ShiitakeError::CommandFailed("No dhcp client is installed".to_string()))?;
+    let status = Command::new(&program).args(*args).arg(interface).status()?;
+    if !status.success() {
+        return Err(ShiitakeError::CommandFailed(format!("{} exited with {}", program.display(), status)));
+    }
+    Ok(())
+}
+
+///the pids of dhcp clients running for the interface
+pub fn dhcp_client_pids(interface: &str) -> Vec<i32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut pids = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<i32>() else {
+            continue;
+        };
+        let Ok(cmdline) = std::fs::read(entry.path().join("cmdline")) else {
+            continue;
+        };
+        let args = cmdline
+            .split(|byte| *byte == 0)
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>();
+        let is_client = args
+            .first()
+            .and_then(|exe| exe.rsplit('/').next())
+            .is_some_and(|exe| DHCP_CLIENTS.iter().any(|(name, _)| *name == exe));
+        if is_client && args.iter().any(|arg| arg == interface) {
+            pids.push(pid);
+        }
+    }
+    pids
+}
+
+///stops any dhcp client running for the interface so it doesn't put its lease back
+fn stop_dhcp(interface: &str) {
+    for pid in dhcp_client_pids(interface) {
+        let _ = nix::sys::signal::kill(Pid::from_raw(pid), Signal::SIGTERM);
+    }
+}
+
+fn read_addresses(interface: &str) -> ShiitakeResult<Vec<Address>> {
+    Ok(parse_addresses(&ip(&["-o", "-4", "addr", "show", "dev", interface])?))
+}
+
+fn read_gateway(interface: &str) -> ShiitakeResult<Option<Ipv4Addr>> {
+    Ok(parse_default_gateway(&ip(&["-4", "route", "show", "default", "dev", interface])?))
+}
+
+pub fn apply(interface: &str, config: &InterfaceConfig) -> ShiitakeResult<()> {
+    stop_dhcp(interface);
+    ip(&["-4", "addr", "flush", "dev", interface])?;
+    ip(&["link", "set", interface, "up"])?;
+    match config {
+        InterfaceConfig::Dhcp => start_dhcp(interface)?,
+        InterfaceConfig::Static { address, prefix, gateway, dns } => {
+            ip(&["addr", "add", &format!("{}/{}", address, prefix), "dev", interface])?;
+            if let Some(gateway) = gateway {
+                ip(&["route", "replace", "default", "via", &gateway.to_string(), "dev", interface])?;
+            }
+            if !dns.is_empty() {
+                let path = resolv_conf_path();
+                let current = std::fs::read_to_string(&path).unwrap_or_default();
+                crate::backups::write_config_file(&path, render_resolv_conf(&current, dns).as_bytes())?;
+            }
+        }
+        InterfaceConfig::LinkLocal => {
+            let mac = std::fs::read_to_string(Path::new(crate::sysfs::NET_ROOT).join(interface).join("address"))?;
+            let address = link_local_address(&mac)
+                .ok_or_else(|| ShiitakeError::BadRequest(format!("{} has no MAC address", interface)))?;
+            ip(&["addr", "add", &format!("{}/16", address), "dev", interface, "scope", "link"])?;
+        }
+    }
+    Ok(())
+}
+
+///how the interface was set up before a change, enough to put it back
+#[derive(Debug, Clone)]
+struct Snapshot {
+    addresses: Vec<Address>,
+    ///every interface's, a new gateway replaces the default route of whichever interface had it
+    default_routes: Vec<inventory::Route>,
+    resolv_conf: Option<String>,
+}
+
+impl Snapshot {
+    fn take(interface: &str) -> ShiitakeResult<Self> {
+        Ok(Snapshot {
+            addresses: read_addresses(interface)?,
+            default_routes: inventory::parse_routes(&ip(&["-o", "-4", "route", "show", "default"])?),
+            resolv_conf: std::fs::read_to_string(resolv_conf_path()).ok(),
+        })
+    }
+
+    ///tries every step even when one fails, a half restored interface is better than one left as the bad change had it
+    fn restore(&self, interface: &str) -> ShiitakeResult<()> {
+        let mut failures = Vec::new();
+        let mut attempt = |result: ShiitakeResult<()>| {
+            if let Err(e) = result {
+                failures.push(describe(&e));
+            }
+        };
+        stop_dhcp(interface);
+        attempt(ip(&["-4", "addr", "flush", "dev", interface]).map(drop));
+        for address in self.addresses.iter().filter(|address| !address.dynamic) {
+            attempt(ip(&["addr", "add", &format!("{}/{}", address.address, address.prefix), "dev", interface]).map(drop));
+        }
+        //a lease can't be re-added by hand, ask for a new one instead
+        let dhcp = self.addresses.iter().any(|address| address.dynamic);
+        if dhcp {
+            attempt(start_dhcp(interface));
+        }
+        for route in &self.default_routes {
+            let (Some(gateway), Some(device)) = (route.gateway, route.interface.as_deref()) else {
+                continue;
+            };
+            if dhcp && device == interface {
+                continue;
+            }
+            let (gateway, metric) = (gateway.to_string(), route.metric.map(|metric| metric.to_string()));
+            let mut args = vec!["route", "replace", "default", "via", &gateway, "dev", device];
+            if let Some(protocol) = &route.protocol {
+                args.extend(["proto", protocol]);
+            }
+            if let Some(metric) = &metric {
+                args.extend(["metric", metric]);
+            }
+            attempt(ip(&args).map(drop));
+        }
+        if let Some(resolv_conf) = &self.resolv_conf {
+            attempt(crate::backups::write_config_file(&resolv_conf_path(), resolv_conf.as_bytes()).map_err(ShiitakeError::from));
+        }
+        if !failures.is_empty() {
+            return Err(ShiitakeError::CommandFailed(failures.join("; ")));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct PendingChange {
+    id: u64,
+    config: InterfaceConfig,
+    ///from before the first unconfirmed change, so reverting always goes back to the last confirmed state
+    snapshot: Snapshot,
+    deadline: Instant,
+    ///set once the config is on the interface, confirming before then would keep a config nobody has reached the server with
+    applied: bool,
+}
+
+#[dynamic]
+static mut PENDING: HashMap<String, PendingChange> = HashMap::new();
+///why the last change to each interface couldn't be applied
+#[dynamic]
+static mut FAILURES: HashMap<String, String> = HashMap::new();
+static NEXT_CHANGE_ID: AtomicU64 = AtomicU64::new(0);
+
+///puts the interface back how it was if change `id` (or any change when `None`) is still unconfirmed
+fn revert(interface: &str, id: Option<u64>) -> ShiitakeResult<bool> {
+    let pending = {
+        let mut pending = PENDING.write();
+        match pending.get(interface) {
+            Some(change) if id.is_none_or(|id| change.id == id) => pending.remove(interface),
+            _ => None,
+        }
+    };
+    let Some(pending) = pending else {
+        return Ok(false);
+    };
+    pending.snapshot.restore(interface)?;
+    Ok(true)
+}
+
+fn describe(e: &ShiitakeError) -> String {
+    match e.detail() {
+        Some(detail) => format!("{}: {}", e, detail),
+        None => e.to_string(),
+    }
+}
+
+async fn apply_then_revert(interface: String, config: InterfaceConfig, id: u64, timeout: Duration) {
+    tokio::time::sleep(APPLY_DELAY).await;
+    if let Err(e) = apply(&interface, &config) {
+        error!("Failed to apply the new config to {}: {}", interface, describe(&e));
+        FAILURES.write().insert(interface.clone(), describe(&e));
+        if let Err(_e) = revert(&interface, Some(id)) {
+            error!("Failed to revert {}: {}", interface, describe(&_e));
+        }
+        return;
+    }
+    if let Some(change) = PENDING.write().get_mut(&interface).filter(|change| change.id == id) {
+        change.applied = true;
+    }
+    tokio::time::sleep(timeout).await;
+    match revert(&interface, Some(id)) {
+        Ok(true) => {
+            warn!("The change to {} wasn't confirmed within {:?}, reverted it", interface, timeout);
+        }
+        Ok(false) => {}
+        Err(_e) => {
+            error!("Failed to revert {}: {}", interface, describe(&_e));
+        }
+    }
+}
+
+fn backend() -> Option<Box<dyn NetworkBackend>> {
+    let config = &crate::config::get().network;
+    let files = BackendFiles {
+        root: config.root.clone(),
+        backups: BackupStore::from_config(),
+    };
+    backends::select(&files, config.backend)
+}
+
+///keeps a confirmed config across reboots, returning where it was saved
+fn persist(interface: &str, config: &InterfaceConfig) -> ShiitakeResult<Option<&'static str>> {
+    //the Rio's own daemons configure eth0 from ni-rt.ini on boot
+    #[cfg(feature = "rio")]
+    if interface == "eth0" {
+        crate::rio_interface::write_eth0(config)?;
+        return Ok(Some("ni-rt.ini"));
+    }
+    let Some(backend) = backend() else {
+        return Ok(None);
+    };
+    backend.write(interface, config)?;
+    Ok(Some(backend.name()))
+}
+
+///where the interface's config is saved and what's saved there
+fn saved(interface: &str) -> ShiitakeResult<(Option<&'static str>, Option<InterfaceConfig>)> {
+    #[cfg(feature = "rio")]
+    if interface == "eth0" {
+        return Ok((Some("ni-rt.ini"), crate::rio_interface::read_eth0()));
+    }
+    match backend() {
+        Some(backend) => Ok((Some(backend.name()), backend.read(interface)?)),
+        None => Ok((None, None)),
+    }
+}
+
+pub fn init_network(app: Router) -> Router {
+    app.route(routes::NETWORK_INTERFACES, get(inventory::get_inventory))
+        .route(routes::NETWORK_NEIGHBORS, get(neighbors::get_neighbors))
+        .route(routes::NETWORK_TEAM, post(team::post_team))
+        .route(routes::NETWORK_INTERFACE, get(get_interface).put(put_interface))
+        .route(routes::NETWORK_INTERFACE_CONFIRM, post(confirm_interface))
+        .route(routes::NETWORK_INTERFACE_REVERT, post(revert_interface))
+        .route(routes::SET_IP, post(set_static_ip))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PendingInfo {
+    config: InterfaceConfig,
+    ///seconds left to confirm the change before it's reverted
+    expires_in: u64,
+    ///whether the interface has the new config yet, it can't be confirmed before then
+    applied: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InterfaceState {
+    interface: String,
+    config: Option<InterfaceConfig>,
+    addresses: Vec<Address>,
+    gateway: Option<Ipv4Addr>,
+    dns: Vec<IpAddr>,
+    pending: Option<PendingInfo>,
+    last_error: Option<String>,
+    ///what confirmed changes are saved with, `None` when they're lost on reboot
+    backend: Option<&'static str>,
+    ///the config the interface comes up with after a reboot
+    saved: Option<InterfaceConfig>,
+}
+
+async fn get_interface(UrlPath(interface): UrlPath<String>) -> ShiitakeResult<Json<InterfaceState>> {
+    check_interface(&interface)?;
+    let addresses = read_addresses(&interface)?;
+    let gateway = read_gateway(&interface)?;
+    let dns = parse_nameservers(&std::fs::read_to_string(resolv_conf_path()).unwrap_or_default());
+    let pending = PENDING.read().get(&interface).map(|change| PendingInfo {
+        config: change.config.clone(),
+        expires_in: change.deadline.saturating_duration_since(Instant::now()).as_secs(),
+        applied: change.applied,
+    });
+    let (backend, saved) = saved(&interface)?;
+    Ok(Json(InterfaceState {
+        config: current_config(&addresses, gateway, dns.clone()),
+        last_error: FAILURES.read().get(&interface).cloned(),
+        interface,
+        addresses,
+        gateway,
+        dns,
+        pending,
+        backend,
+        saved,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NetworkRequest {
+    #[serde(flatten)]
+    config: InterfaceConfig,
+    ///seconds to wait for `/network/:interface/confirm` before reverting
+    confirm_timeout: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangeResponse {
+    confirm_within: u64,
+    message: String,
+}
+
+async fn put_interface(
+    UrlPath(interface): UrlPath<String>,
+    Json(request): Json<NetworkRequest>,
+) -> ShiitakeResult<Json<ChangeResponse>> {
+    start_change(interface, request.config, request.confirm_timeout).map(Json)
+}
+
+///the body `/set_ip` has always taken, a /24 on the gateway's network
+#[derive(Deserialize)]
+struct StaticIpConfig {
+    ///`eth0` when not set
+    interface: Option<String>,
+    ip: Ipv4Addr,
+    gateway: Ipv4Addr,
+}
+
+impl StaticIpConfig {
+    fn interface_config(&self) -> InterfaceConfig {
+        InterfaceConfig::Static {
+            address: self.ip,
+            prefix: 24,
+            gateway: Some(self.gateway),
+            dns: Vec::new(),
+        }
+    }
+}
+
+async fn set_static_ip(Json(config): Json<StaticIpConfig>) -> ShiitakeResult<Json<ChangeResponse>> {
+    let interface_config = config.interface_config();
+    let interface = config.interface.unwrap_or_else(|| String::from("eth0"));
+    start_change(interface, interface_config, None).map(Json)
+}
+
+///applies the config once the response is sent, reverting it if it isn't confirmed within the timeout
+fn start_change(interface: String, config: InterfaceConfig, confirm_timeout: Option<u64>) -> ShiitakeResult<ChangeResponse> {
+    check_interface(&interface)?;
+    config.validate()?;
+    let timeout = confirm_timeout.unwrap_or(DEFAULT_CONFIRM_TIMEOUT);
+    if !(MIN_CONFIRM_TIMEOUT..=MAX_CONFIRM_TIMEOUT).contains(&timeout) {
+        return Err(ShiitakeError::BadRequest(format!(
+            "confirmTimeout must be between {} and {} seconds",
+            MIN_CONFIRM_TIMEOUT, MAX_CONFIRM_TIMEOUT
+        )));
+    }
+
+    let snapshot = match PENDING.read().get(&interface) {
+        Some(change) => change.snapshot.clone(),
+        None => Snapshot::take(&interface)?,
+    };
+    let id = NEXT_CHANGE_ID.fetch_add(1, Ordering::Relaxed);
+    let timeout = Duration::from_secs(timeout);
+    FAILURES.write().remove(&interface);
+    PENDING.write().insert(
+        interface.clone(),
+        PendingChange {
+            id,
+            config: config.clone(),
+            snapshot,
+            deadline: Instant::now() + APPLY_DELAY + timeout,
+            applied: false,
+        },
+    );
+    //applied after responding, the connection this request came in on may not survive the change
+    tokio::spawn(apply_then_revert(interface.clone(), config, id, timeout));
+    Ok(ChangeResponse {
+        confirm_within: timeout.as_secs(),
+        message: format!(
+            "Applying the change to {}, POST {} within {} seconds to keep it",
+            interface,
+            routes::NETWORK_INTERFACE_CONFIRM.replace(":interface", &interface),
+            timeout.as_secs()
+        ),
+    })
+}
+
+async fn confirm_interface(UrlPath(interface): UrlPath<String>) -> ShiitakeResult<String> {
+    check_interface(&interface)?;
+    let change = {
+        let mut pending = PENDING.write();
+        match pending.get(&interface) {
+            None => return Err(ShiitakeError::NotFound(format!("No unconfirmed change to {}", interface))),
+            Some(change) if !change.applied => {
+                return Err(ShiitakeError::Conflict(format!(
+                    "The change to {} hasn't been applied yet, confirm it from its new address",
+                    interface
+                )))
+            }
+            Some(_) => pending.remove(&interface).unwrap(),
+        }
+    };
+    match persist(&interface, &change.config)? {
+        Some(backend) => Ok(format!("Network change confirmed and saved with {}", backend)),
+        None => {
+            warn!("No network backend found, the change to {} will be lost on reboot", interface);
+            Ok("Network change confirmed, no network backend was found to save it with".to_string())
+        }
+    }
+}
+
+async fn revert_interface(UrlPath(interface): UrlPath<String>) -> ShiitakeResult<&'static str> {
+    check_interface(&interface)?;
+    if !revert(&interface, None)? {
+        return Err(ShiitakeError::NotFound(format!("No unconfirmed change to {}", interface)));
+    }
+    Ok("Network change reverted")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn static_config(address: &str, prefix: u8, gateway: Option<&str>) -> InterfaceConfig {
+        InterfaceConfig::Static {
+            address: address.parse().unwrap(),
+            prefix,
+            gateway: gateway.map(|gateway| gateway.parse().unwrap()),
+            dns: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(static_config("10.64.64.2", 24, Some("10.64.64.1")).validate().is_ok());
+        assert!(static_config("10.64.64.2", 8, None).validate().is_ok());
+        assert!(static_config("10.64.64.2", 0, None).validate().is_err());
+        assert!(static_config("10.64.64.2", 33, None).validate().is_err());
+        assert!(static_config("10.64.64.0", 24, None).validate().is_err());
+        assert!(static_config("10.64.64.255", 24, None).validate().is_err());
+        assert!(static_config("224.0.0.1", 24, None).validate().is_err());
+        assert!(static_config("10.64.64.2", 24, Some("10.64.65.1")).validate().is_err());
+        assert!(static_config("10.64.64.2", 24, Some("10.64.64.2")).validate().is_err());
+
+        let config: InterfaceConfig = serde_json::from_str(
+            r#"{"mode": "static", "address": "10.64.64.2", "prefix": 24, "dns": ["1.1.1.1", "8.8.8.8", "9.9.9.9", "8.8.4.4"]}"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+        assert_eq!(prefix_to_mask(24), Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(prefix_to_mask(32), Ipv4Addr::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn test_request() {
+        let request: NetworkRequest =
+            serde_json::from_str(r#"{"mode": "dhcp", "confirmTimeout": 60}"#).unwrap();
+        assert_eq!(request.config, InterfaceConfig::Dhcp);
+        assert_eq!(request.confirm_timeout, Some(60));
+        assert!(serde_json::from_str::<NetworkRequest>(r#"{"mode": "static", "address": "10.64.64.2"}"#).is_err());
+
+        let request: StaticIpConfig = serde_json::from_str(r#"{"ip": "10.64.64.2", "gateway": "10.64.64.1"}"#).unwrap();
+        assert_eq!(request.interface, None);
+        assert_eq!(request.interface_config(), static_config("10.64.64.2", 24, Some("10.64.64.1")));
+    }
+
+    #[test]
+    fn test_parse_ip_output() {
+        let addresses = parse_addresses(
+            "4: eth0    inet 10.64.64.2/24 brd 10.64.64.255 scope global dynamic eth0\\       valid_lft 86000sec preferred_lft 86000sec\n\
+             4: eth0    inet 169.254.84.93/16 brd 169.254.255.255 scope link eth0\\       valid_lft forever preferred_lft forever\n",
+        );
+        assert_eq!(
+            addresses,
+            vec![
+                Address { address: Ipv4Addr::new(10, 64, 64, 2), prefix: 24, dynamic: true },
+                Address { address: Ipv4Addr::new(169, 254, 84, 93), prefix: 16, dynamic: false },
+            ]
+        );
+        assert_eq!(current_config(&addresses, None, Vec::new()), Some(InterfaceConfig::Dhcp));
+        assert_eq!(current_config(&addresses[1..], None, Vec::new()), Some(InterfaceConfig::LinkLocal));
+        assert_eq!(
+            parse_default_gateway("default via 10.64.64.1 dev eth0 proto dhcp metric 100\n"),
+            Some(Ipv4Addr::new(10, 64, 64, 1))
+        );
+        assert_eq!(parse_default_gateway(""), None);
+    }
+
+    #[test]
+    fn test_resolv_conf() {
+        let current = "# generated\nsearch frc.local\nnameserver 10.64.64.1\noptions edns0\n";
+        let dns = vec!["1.1.1.1".parse().unwrap(), "2606:4700::1111".parse().unwrap()];
+        let rendered = render_resolv_conf(current, &dns);
+        assert_eq!(
+            rendered,
+            "# generated\nsearch frc.local\noptions edns0\nnameserver 1.1.1.1\nnameserver 2606:4700::1111\n"
+        );
+        assert_eq!(parse_nameservers(&rendered), dns);
+        assert_eq!(link_local_address("00:80:2f:25:7e:70"), Some(Ipv4Addr::new(169, 254, 127, 112)));
+        assert_eq!(link_local_address("bogus"), None);
+    }
+}
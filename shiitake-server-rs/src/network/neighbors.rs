@@ -0,0 +1,386 @@
+//! What else is on the robot network, from the kernel's neighbor table.
+//! Hosts are told apart by their MAC vendor, their place on the team network,
+//! whether they run FarFetched or the NI webserver and whether they're connected to the roboRIO as a driver station.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use axum::{extract::Query, http::HeaderMap, Json};
+use serde::{Deserialize, Serialize};
+use static_init::dynamic;
+
+use super::inventory::parse_routes;
+use super::ip;
+use super::team::{current_team, team_address, team_from_hostname};
+use crate::types::routes;
+use crate::ShiitakeResult;
+
+///the port the NI webserver serves on, and FarFetched unless it's been moved
+pub const PROBE_PORT: u16 = 80;
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+///a system summary is well under this, anything longer isn't FarFetched
+const MAX_PROBE_RESPONSE: u64 = 64 * 1024;
+pub const MAX_PROBES: usize = 32;
+///probes are reused for this long so refreshing the page doesn't reach out to every host again
+const PROBE_CACHE_TTL: Duration = Duration::from_secs(10);
+///the driver station connects to the roboRIO on this port
+pub const DRIVER_STATION_PORT: u16 = 1740;
+///the static address FRC recommends for the driver station
+const DRIVER_STATION_HOST: u8 = 5;
+///the radio is always `.1`
+const RADIO_HOST: u8 = 1;
+
+pub const NATIONAL_INSTRUMENTS: &str = "National Instruments";
+pub const OPEN_MESH: &str = "Open Mesh";
+
+///vendors of the devices usually found on a robot network, by MAC prefix
+const OUI_VENDORS: &[(&str, &str)] = &[
+    ("00:80:2f", NATIONAL_INSTRUMENTS),
+    ("ac:86:74", OPEN_MESH),
+    ("b8:27:eb", "Raspberry Pi"),
+    ("dc:a6:32", "Raspberry Pi"),
+    ("e4:5f:01", "Raspberry Pi"),
+    ("28:cd:c1", "Raspberry Pi"),
+    ("d8:3a:dd", "Raspberry Pi"),
+    ("2c:cf:67", "Raspberry Pi"),
+    ("00:04:4b", "NVIDIA"),
+    ("48:b0:2d", "NVIDIA"),
+];
+
+#[dynamic]
+static mut PROBES: HashMap<IpAddr, (Instant, Probe)> = HashMap::new();
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighborRole {
+    Radio,
+    DriverStation,
+    Roborio,
+    ///runs FarFetched and isn't a roboRIO
+    Coprocessor,
+    Unknown,
+}
+
+///another FarFetched instance's summary
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FarFetched {
+    pub hostname: String,
+    pub shiitake_version: String,
+    #[serde(default)]
+    pub team: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Probe {
+    FarFetched(FarFetched),
+    ///answered `/nisysdetails/ping` without serving FarFetched
+    NiWebserver,
+    Nothing,
+}
+
+///one entry of the neighbor table
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeighborEntry {
+    pub address: IpAddr,
+    pub interface: String,
+    pub mac: Option<String>,
+    ///`REACHABLE`, `STALE`, `FAILED`...
+    pub state: String,
+    ///advertises itself as an IPv6 router
+    pub router: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Neighbor {
+    pub address: IpAddr,
+    pub interface: String,
+    pub mac: Option<String>,
+    pub vendor: Option<&'static str>,
+    pub state: String,
+    pub role: NeighborRole,
+    pub farfetched: Option<FarFetched>,
+    pub ni_webserver: bool,
+}
+
+///the neighbors in `ip -o neigh show` output, `10.64.64.1 dev eth0 lladdr 00:80:2f:25:7e:70 router REACHABLE`
+pub fn parse_neighbors(output: &str) -> Vec<NeighborEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let after = |key: &str| {
+                let i = words.iter().position(|word| *word == key)?;
+                words.get(i + 1).map(|word| word.to_string())
+            };
+            Some(NeighborEntry {
+                address: words.first()?.parse().ok()?,
+                interface: after("dev")?,
+                mac: after("lladdr").map(|mac| mac.to_lowercase()),
+                state: words.last()?.to_string(),
+                router: words.contains(&"router"),
+            })
+        })
+        .collect()
+}
+
+pub fn mac_vendor(mac: &str) -> Option<&'static str> {
+    let prefix = mac.get(..8)?.to_lowercase();
+    OUI_VENDORS
+        .iter()
+        .find(|(oui, _)| *oui == prefix)
+        .map(|(_, vendor)| *vendor)
+}
+
+///the last byte of an address on the team's `10.TE.AM.0/24` network
+fn team_host(address: IpAddr, team: Option<u16>) -> Option<u8> {
+    let IpAddr::V4(address) = address else {
+        return None;
+    };
+    let network = team_address(team?, 0).ok()?;
+    (address.octets()[..3] == network.octets()[..3]).then_some(address.octets()[3])
+}
+
+///everything known about a neighbor that says what it is
+pub struct Evidence<'a> {
+    pub address: IpAddr,
+    pub vendor: Option<&'static str>,
+    pub probe: &'a Probe,
+    ///the next hop of one of the default routes
+    pub gateway: bool,
+    ///connected to the driver station port of this roboRIO
+    pub driver_station_peer: bool,
+    pub team: Option<u16>,
+}
+
+pub fn classify(evidence: &Evidence) -> NeighborRole {
+    let host = team_host(evidence.address, evidence.team);
+    let ni = evidence.vendor == Some(NATIONAL_INSTRUMENTS);
+    match evidence.probe {
+        Probe::FarFetched(summary) if ni || team_from_hostname(&summary.hostname).is_some() => NeighborRole::Roborio,
+        Probe::FarFetched(_) => NeighborRole::Coprocessor,
+        Probe::NiWebserver => NeighborRole::Roborio,
+        Probe::Nothing if ni => NeighborRole::Roborio,
+        Probe::Nothing if evidence.driver_station_peer => NeighborRole::DriverStation,
+        Probe::Nothing if evidence.gateway || evidence.vendor == Some(OPEN_MESH) || host == Some(RADIO_HOST) => {
+            NeighborRole::Radio
+        }
+        Probe::Nothing if host == Some(DRIVER_STATION_HOST) => NeighborRole::DriverStation,
+        Probe::Nothing => NeighborRole::Unknown,
+    }
+}
+
+///status code and body of the response to a `GET`, `None` if the host didn't answer with HTTP
+fn http_get(address: IpAddr, port: u16, path: &str) -> Option<(u16, String)> {
+    let mut stream = TcpStream::connect_timeout(&SocketAddr::new(address, port), PROBE_TIMEOUT).ok()?;
+    stream.set_read_timeout(Some(PROBE_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(PROBE_TIMEOUT)).ok()?;
+    //HTTP/1.0 so the body is never chunked
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, address).ok()?;
+    let mut response = Vec::new();
+    stream.take(MAX_PROBE_RESPONSE).read_to_end(&mut response).ok()?;
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    let status = head.lines().next()?.split_whitespace().nth(1)?.parse().ok()?;
+    Some((status, body.to_string()))
+}
+
+///`PROBE_PORT` then the ports this server listens on, other hosts running FarFetched are likely set up the same way
+pub fn probe_ports(listen: &[SocketAddr]) -> Vec<u16> {
+    let mut ports = vec![PROBE_PORT];
+    for addr in listen {
+        if !ports.contains(&addr.port()) {
+            ports.push(addr.port());
+        }
+    }
+    ports
+}
+
+fn probe(address: IpAddr, ports: &[u16]) -> Probe {
+    for port in ports {
+        if let Some((200, body)) = http_get(address, *port, routes::SYSTEM_SUMMARY) {
+            if let Ok(summary) = serde_json::from_str::<FarFetched>(&body) {
+                return Probe::FarFetched(summary);
+            }
+        }
+    }
+    match http_get(address, PROBE_PORT, routes::RIO_PING) {
+        Some((200, _)) => Probe::NiWebserver,
+        _ => Probe::Nothing,
+    }
+}
+
+///probes every address at once, reusing recent results
+async fn probe_all(addresses: Vec<IpAddr>) -> HashMap<IpAddr, Probe> {
+    let now = Instant::now();
+    PROBES
+        .write()
+        .retain(|_, (probed, _)| now.duration_since(*probed) < PROBE_CACHE_TTL);
+    let cached = PROBES.read().keys().copied().collect::<Vec<_>>();
+    let ports = probe_ports(&crate::config::get().server.listen);
+    let probes = addresses
+        .into_iter()
+        .filter(|address| !cached.contains(address))
+        .map(|address| {
+            let ports = ports.clone();
+            async move {
+                let probe = tokio::task::spawn_blocking(move || probe(address, &ports))
+                    .await
+                    .unwrap_or(Probe::Nothing);
+                (address, probe)
+            }
+        });
+    let fresh = futures_util::future::join_all(probes).await;
+    let mut cache = PROBES.write();
+    for (address, probe) in fresh {
+        cache.insert(address, (Instant::now(), probe));
+    }
+    cache.iter().map(|(address, (_, probe))| (*address, probe.clone())).collect()
+}
+
+fn driver_station_peers() -> Vec<IpAddr> {
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|path| crate::procfs::read_proc_file(path).ok())
+        .flat_map(|file| crate::procfs::parse_tcp_peers(&file, DRIVER_STATION_PORT))
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub(super) struct NeighborsQuery {
+    ///`false` to only read the neighbor table, the default unless the caller could change the system anyway
+    probe: Option<bool>,
+}
+
+pub(super) async fn get_neighbors(
+    headers: HeaderMap,
+    Query(query): Query<NeighborsQuery>,
+) -> ShiitakeResult<Json<Vec<Neighbor>>> {
+    //hosts the kernel couldn't reach aren't there anymore
+    let mut entries = parse_neighbors(&ip(&["-o", "neigh", "show"])?)
+        .into_iter()
+        .filter(|entry| entry.mac.is_some() && entry.state != "FAILED" && entry.state != "INCOMPLETE")
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.address);
+    let gateways = parse_routes(&ip(&["-o", "-4", "route", "show", "default"])?)
+        .into_iter()
+        .filter_map(|route| route.gateway)
+        .collect::<Vec<_>>();
+    let peers = driver_station_peers();
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    let team = current_team(&hostname);
+
+    //only IPv4, IPv6 neighbors are mostly the link local addresses of the same hosts
+    let mut probes = HashMap::new();
+    //reading is public by default, so an anonymous page load shouldn't make the server reach out to every host
+    if query.probe.unwrap_or_else(|| crate::auth::is_trusted(&headers)) {
+        let addresses = entries
+            .iter()
+            .map(|entry| entry.address)
+            .filter(IpAddr::is_ipv4)
+            .take(MAX_PROBES)
+            .collect();
+        probes = probe_all(addresses).await;
+    }
+
+    let neighbors = entries
+        .into_iter()
+        .map(|entry| {
+            let vendor = entry.mac.as_deref().and_then(mac_vendor);
+            let probe = probes.remove(&entry.address).unwrap_or(Probe::Nothing);
+            let role = classify(&Evidence {
+                address: entry.address,
+                vendor,
+                probe: &probe,
+                gateway: entry.router || gateways.contains(&entry.address),
+                driver_station_peer: peers.contains(&entry.address),
+                team,
+            });
+            Neighbor {
+                address: entry.address,
+                interface: entry.interface,
+                mac: entry.mac,
+                vendor,
+                state: entry.state,
+                role,
+                ni_webserver: probe == Probe::NiWebserver,
+                farfetched: match probe {
+                    Probe::FarFetched(summary) => Some(summary),
+                    _ => None,
+                },
+            }
+        })
+        .collect();
+    Ok(Json(neighbors))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_neighbors() {
+        let output = "10.64.64.1 dev eth0 lladdr AC:86:74:01:02:03 REACHABLE\n\
+                      10.64.64.77 dev eth0 FAILED\n\
+                      fe80::1 dev eth0 lladdr 00:80:2f:25:7e:70 router STALE\n";
+        let neighbors = parse_neighbors(output);
+        assert_eq!(neighbors.len(), 3);
+        assert_eq!(neighbors[0].mac.as_deref(), Some("ac:86:74:01:02:03"));
+        assert_eq!(neighbors[0].state, "REACHABLE");
+        assert!(!neighbors[0].router);
+        assert_eq!(neighbors[1].mac, None);
+        assert_eq!(neighbors[1].state, "FAILED");
+        assert!(neighbors[2].router);
+        assert_eq!(mac_vendor("00:80:2F:25:7E:70"), Some(NATIONAL_INSTRUMENTS));
+        assert_eq!(mac_vendor("02:fc:00:00:00:05"), None);
+    }
+
+    #[test]
+    fn test_probe_ports() {
+        assert_eq!(probe_ports(&["0.0.0.0:80".parse().unwrap()]), vec![80]);
+        let listen = ["0.0.0.0:5800".parse().unwrap(), "[::]:5800".parse().unwrap(), "127.0.0.1:8080".parse().unwrap()];
+        assert_eq!(probe_ports(&listen), vec![80, 5800, 8080]);
+    }
+
+    #[test]
+    fn test_classify() {
+        let evidence = |address: &str, vendor, probe| Evidence {
+            address: address.parse().unwrap(),
+            vendor,
+            probe,
+            gateway: false,
+            driver_station_peer: false,
+            team: Some(6464),
+        };
+        let farfetched = |hostname: &str| {
+            Probe::FarFetched(FarFetched {
+                hostname: hostname.to_string(),
+                shiitake_version: "0.1.1".to_string(),
+                team: Some(6464),
+            })
+        };
+        let (nothing, roborio, vision) = (Probe::Nothing, farfetched("roboRIO-6464-FRC"), farfetched("vision"));
+
+        assert_eq!(classify(&evidence("10.64.64.1", None, &nothing)), NeighborRole::Radio);
+        assert_eq!(classify(&evidence("192.168.1.1", Some(OPEN_MESH), &nothing)), NeighborRole::Radio);
+        assert_eq!(classify(&evidence("10.64.64.2", Some(NATIONAL_INSTRUMENTS), &nothing)), NeighborRole::Roborio);
+        assert_eq!(classify(&evidence("10.64.64.2", None, &Probe::NiWebserver)), NeighborRole::Roborio);
+        assert_eq!(classify(&evidence("10.64.64.2", None, &roborio)), NeighborRole::Roborio);
+        assert_eq!(classify(&evidence("10.64.64.11", Some("Raspberry Pi"), &vision)), NeighborRole::Coprocessor);
+        assert_eq!(classify(&evidence("10.64.64.5", None, &nothing)), NeighborRole::DriverStation);
+        assert_eq!(classify(&evidence("10.64.64.201", None, &nothing)), NeighborRole::Unknown);
+        let connected = Evidence {
+            driver_station_peer: true,
+            ..evidence("10.64.64.201", None, &nothing)
+        };
+        assert_eq!(classify(&connected), NeighborRole::DriverStation);
+        let gateway = Evidence {
+            gateway: true,
+            ..evidence("10.0.0.1", None, &nothing)
+        };
+        assert_eq!(classify(&gateway), NeighborRole::Radio);
+    }
+}
@@ -0,0 +1,150 @@
+//! Addressing a device by its FRC team number and role,
+//! every robot network is `10.TE.AM.0/24` with the radio at `.1` and the roboRIO at `.2`.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use axum::Json;
+use serde::Deserialize;
+
+use super::{start_change, ChangeResponse, InterfaceConfig};
+use crate::{ShiitakeError, ShiitakeResult};
+
+///`TE` is the address's second byte, so 255 is the highest
+pub const MAX_TEAM: u16 = 25599;
+///static devices go in `.6`-`.19`, below the radio's dhcp pool and clear of the driver station
+pub const COPROCESSOR_SLOTS: u8 = 5;
+pub const CAMERA_SLOTS: u8 = 4;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum TeamRole {
+    ///`10.TE.AM.2`
+    Roborio,
+    ///`10.TE.AM.11`-`10.TE.AM.15`, slot 1 is where a Limelight sits by default
+    Coprocessor { slot: u8 },
+    ///`10.TE.AM.16`-`10.TE.AM.19`
+    Camera { slot: u8 },
+}
+
+impl TeamRole {
+    ///the last byte of the role's address
+    fn host(&self) -> ShiitakeResult<u8> {
+        let (slot, slots, first) = match *self {
+            TeamRole::Roborio => return Ok(2),
+            TeamRole::Coprocessor { slot } => (slot, COPROCESSOR_SLOTS, 11),
+            TeamRole::Camera { slot } => (slot, CAMERA_SLOTS, 16),
+        };
+        if !(1..=slots).contains(&slot) {
+            return Err(ShiitakeError::BadRequest(format!("slot must be between 1 and {}", slots)));
+        }
+        Ok(first + slot - 1)
+    }
+}
+
+///`10.TE.AM.host`
+pub fn team_address(team: u16, host: u8) -> ShiitakeResult<Ipv4Addr> {
+    if !(1..=MAX_TEAM).contains(&team) {
+        return Err(ShiitakeError::BadRequest(format!("team must be between 1 and {}", MAX_TEAM)));
+    }
+    Ok(Ipv4Addr::new(10, (team / 100) as u8, (team % 100) as u8, host))
+}
+
+///a static config on the team's network with the radio as gateway and nameserver
+pub fn team_config(team: u16, role: TeamRole) -> ShiitakeResult<InterfaceConfig> {
+    let radio = team_address(team, 1)?;
+    Ok(InterfaceConfig::Static {
+        address: team_address(team, role.host()?)?,
+        prefix: 24,
+        gateway: Some(radio),
+        dns: vec![IpAddr::V4(radio)],
+    })
+}
+
+///the team in a `roboRIO-TEAM-FRC` hostname
+pub fn team_from_hostname(hostname: &str) -> Option<u16> {
+    let lower = hostname.trim().to_ascii_lowercase();
+    let team = lower.strip_prefix("roborio-")?.strip_suffix("-frc")?;
+    team.parse().ok().filter(|team| (1..=MAX_TEAM).contains(team))
+}
+
+///the team whose network a `10.TE.AM.x/24` address is on
+pub fn team_from_address(address: Ipv4Addr, prefix: u8) -> Option<u16> {
+    let [ten, te, am, _] = address.octets();
+    if ten != 10 || prefix != 24 || am > 99 {
+        return None;
+    }
+    Some(te as u16 * 100 + am as u16).filter(|team| *team != 0)
+}
+
+///the team from the hostname on a roboRIO, otherwise from the first address on a team network
+pub fn current_team(hostname: &str) -> Option<u16> {
+    team_from_hostname(hostname).or_else(|| {
+        nix::ifaddrs::getifaddrs().ok()?.find_map(|interface| {
+            let address = interface.address?.as_sockaddr_in()?.ip();
+            let prefix = u32::from(interface.netmask?.as_sockaddr_in()?.ip()).count_ones() as u8;
+            team_from_address(address, prefix)
+        })
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct TeamRequest {
+    team: u16,
+    #[serde(flatten)]
+    role: TeamRole,
+    ///`eth0` when not set
+    interface: Option<String>,
+    ///seconds to wait for `/network/:interface/confirm` before reverting
+    confirm_timeout: Option<u64>,
+}
+
+pub(super) async fn post_team(Json(request): Json<TeamRequest>) -> ShiitakeResult<Json<ChangeResponse>> {
+    let config = team_config(request.team, request.role)?;
+    let interface = request.interface.unwrap_or_else(|| String::from("eth0"));
+    start_change(interface, config, request.confirm_timeout).map(Json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_team_config() {
+        let config = team_config(6464, TeamRole::Coprocessor { slot: 1 }).unwrap();
+        assert_eq!(
+            config,
+            InterfaceConfig::Static {
+                address: Ipv4Addr::new(10, 64, 64, 11),
+                prefix: 24,
+                gateway: Some(Ipv4Addr::new(10, 64, 64, 1)),
+                dns: vec![IpAddr::V4(Ipv4Addr::new(10, 64, 64, 1))],
+            }
+        );
+        assert!(config.validate().is_ok());
+        assert_eq!(team_address(254, 2).unwrap(), Ipv4Addr::new(10, 2, 54, 2));
+        assert_eq!(team_address(25599, 2).unwrap(), Ipv4Addr::new(10, 255, 99, 2));
+        assert_eq!(TeamRole::Camera { slot: 4 }.host().unwrap(), 19);
+        assert!(team_address(0, 2).is_err());
+        assert!(team_address(25600, 2).is_err());
+        assert!(team_config(6464, TeamRole::Coprocessor { slot: 0 }).is_err());
+        assert!(team_config(6464, TeamRole::Camera { slot: 5 }).is_err());
+
+        let request: TeamRequest = serde_json::from_str(r#"{"team": 6464, "role": "camera", "slot": 2}"#).unwrap();
+        assert_eq!(request.role, TeamRole::Camera { slot: 2 });
+        let request: TeamRequest = serde_json::from_str(r#"{"team": 6464, "role": "roborio"}"#).unwrap();
+        assert_eq!(request.role, TeamRole::Roborio);
+    }
+
+    #[test]
+    fn test_detect_team() {
+        assert_eq!(team_from_hostname("roboRIO-6464-FRC"), Some(6464));
+        assert_eq!(team_from_hostname("roborio-254-frc\n"), Some(254));
+        assert_eq!(team_from_hostname("raspberrypi"), None);
+        assert_eq!(team_from_address(Ipv4Addr::new(10, 64, 64, 11), 24), Some(6464));
+        assert_eq!(team_from_address(Ipv4Addr::new(10, 0, 1, 2), 24), Some(1));
+        assert_eq!(team_from_address(Ipv4Addr::new(10, 0, 0, 2), 24), None);
+        assert_eq!(team_from_address(Ipv4Addr::new(10, 64, 64, 11), 8), None);
+        assert_eq!(team_from_address(Ipv4Addr::new(192, 168, 1, 2), 24), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! Endpoints for acting on a process from the web UI: signalling, renicing and changing its scheduling.
+
+use std::str::FromStr;
+
+use axum::{
+    extract::Path,
+    routing::post,
+    Json, Router,
+};
+use nix::{
+    errno::Errno,
+    sched::CpuSet,
+    sys::signal::Signal,
+    unistd::Pid,
+};
+use serde::Deserialize;
+use static_init::dynamic;
+
+use crate::procfs;
+use crate::types::routes;
+use crate::{ShiitakeError, ShiitakeResult};
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::warn!($($arg)*);
+    }
+}
+
+///NI's system daemons, killing any of these leaves the roboRIO unreachable until a power cycle
+#[cfg(feature = "rio")]
+pub const DEFAULT_PROTECTED_NAMES: &[&str] = &[
+    "init", "systemd", "lvrt", "NiRioRpc", "nirio_emb_can", "niauth", "nisvcloc", "sshd", "syslog-ng",
+];
+#[cfg(not(feature = "rio"))]
+pub const DEFAULT_PROTECTED_NAMES: &[&str] = &["init", "systemd", "sshd"];
+
+///Processes that can't be signalled or rescheduled from the web UI.
+///Pids 1 and 2, kernel threads and the server itself are always protected on top of `names`.
+#[derive(Debug, Clone)]
+pub struct ProtectedProcesses {
+    pub names: Vec<String>,
+}
+
+impl Default for ProtectedProcesses {
+    fn default() -> Self {
+        ProtectedProcesses {
+            names: DEFAULT_PROTECTED_NAMES.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+impl ProtectedProcesses {
+    ///the built in names plus the config's `protected_processes`
+    pub fn from_config() -> Self {
+        let mut protected = ProtectedProcesses::default();
+        for name in &crate::config::get().protected_processes {
+            if !protected.names.contains(name) {
+                protected.names.push(name.clone());
+            }
+        }
+        protected
+    }
+
+    pub fn is_protected(&self, pid: u32) -> bool {
+        if pid <= 2 || pid == std::process::id() {
+            return true;
+        }
+        let Some(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .ok()
+            .and_then(|file| procfs::parse_pid_stat(&file))
+        else {
+            //a process that can't be inspected can't be shown to be safe to touch
+            return true;
+        };
+        //children of kthreadd are kernel threads
+        stat.ppid == 2 || self.names.contains(&stat.comm)
+    }
+}
+
+//lazy so the config is loaded by the time it's read
+#[dynamic(lazy)]
+pub static PROTECTED_PROCESSES: ProtectedProcesses = ProtectedProcesses::from_config();
+
+pub fn init_process_control(app: Router) -> Router {
+    let control = Router::new()
+        .route(routes::PROCESS_SIGNAL, post(signal_process))
+        .route(routes::PROCESS_RENICE, post(renice_process))
+        .route(routes::PROCESS_SCHED, post(sched_process))
+        .route(routes::PROCESS_AFFINITY, post(affinity_process));
+    app.merge(control)
+}
+
+fn check_protected(pid: u32) -> ShiitakeResult<Pid> {
+    //kill treats 0 as the server's process group and negative pids as every process or a whole group
+    if pid == 0 || pid > i32::MAX as u32 {
+        return Err(ShiitakeError::BadRequest(format!("{} isn't a valid pid", pid)));
+    }
+    if !std::path::Path::new(&format!("/proc/{}", pid)).exists() {
+        return Err(ShiitakeError::NotFound(format!("No process {}", pid)));
+    }
+    if PROTECTED_PROCESSES.is_protected(pid) {
+        warn!("Refusing to touch protected process {}", pid);
+        return Err(ShiitakeError::Forbidden(format!("Process {} is protected", pid)));
+    }
+    Ok(Pid::from_raw(pid as i32))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SignalSpec {
+    Name(String),
+    Number(i32),
+}
+
+#[derive(Deserialize)]
+struct SignalRequest {
+    signal: SignalSpec,
+}
+
+async fn signal_process(Path(pid): Path<u32>, Json(request): Json<SignalRequest>) -> ShiitakeResult<&'static str> {
+    let signal = match request.signal {
+        SignalSpec::Name(name) => {
+            let name = name.to_uppercase();
+            if name.starts_with("SIG") {
+                Signal::from_str(&name)
+            } else {
+                Signal::from_str(&format!("SIG{}", name))
+            }
+        }
+        SignalSpec::Number(number) => Signal::try_from(number),
+    }
+    .map_err(|_| ShiitakeError::BadRequest("Unknown signal".to_string()))?;
+    let pid = check_protected(pid)?;
+    nix::sys::signal::kill(pid, signal)?;
+    Ok("Signal sent")
+}
+
+#[derive(Deserialize)]
+struct ReniceRequest {
+    nice: i32,
+}
+
+async fn renice_process(Path(pid): Path<u32>, Json(request): Json<ReniceRequest>) -> ShiitakeResult<&'static str> {
+    if !(-20..=19).contains(&request.nice) {
+        return Err(ShiitakeError::BadRequest("Nice must be between -20 and 19".to_string()));
+    }
+    let pid = check_protected(pid)?;
+    //nix doesn't wrap setpriority
+    let result = unsafe { nix::libc::setpriority(nix::libc::PRIO_PROCESS, pid.as_raw() as nix::libc::id_t, request.nice) };
+    Errno::result(result)?;
+    Ok("Process reniced")
+}
+
+#[derive(Deserialize)]
+struct SchedRequest {
+    policy: String,
+    #[serde(default)]
+    priority: i32,
+}
+
+fn parse_sched_policy(policy: &str) -> Option<i32> {
+    match policy.to_uppercase().trim_start_matches("SCHED_") {
+        "OTHER" | "NORMAL" => Some(nix::libc::SCHED_OTHER),
+        "FIFO" => Some(nix::libc::SCHED_FIFO),
+        "RR" => Some(nix::libc::SCHED_RR),
+        "BATCH" => Some(nix::libc::SCHED_BATCH),
+        "IDLE" => Some(nix::libc::SCHED_IDLE),
+        _ => None,
+    }
+}
+
+async fn sched_process(Path(pid): Path<u32>, Json(request): Json<SchedRequest>) -> ShiitakeResult<&'static str> {
+    let policy = parse_sched_policy(&request.policy)
+        .ok_or_else(|| ShiitakeError::BadRequest(format!("Unknown scheduling policy {}", request.policy)))?;
+    let realtime = policy == nix::libc::SCHED_FIFO || policy == nix::libc::SCHED_RR;
+    //realtime policies need a priority in 1..=99, everything else requires 0
+    if (realtime && !(1..=99).contains(&request.priority)) || (!realtime && request.priority != 0) {
+        return Err(ShiitakeError::BadRequest("Priority must be 1-99 for realtime policies and 0 otherwise".to_string()));
+    }
+    let pid = check_protected(pid)?;
+    let param = nix::libc::sched_param {
+        sched_priority: request.priority,
+    };
+    //nix doesn't wrap sched_setscheduler
+    let result = unsafe { nix::libc::sched_setscheduler(pid.as_raw(), policy, &param) };
+    Errno::result(result)?;
+    Ok("Scheduling policy set")
+}
+
+#[derive(Deserialize)]
+struct AffinityRequest {
+    cpus: Vec<usize>,
+}
+
+async fn affinity_process(Path(pid): Path<u32>, Json(request): Json<AffinityRequest>) -> ShiitakeResult<&'static str> {
+    if request.cpus.is_empty() {
+        return Err(ShiitakeError::BadRequest("At least one cpu is required".to_string()));
+    }
+    let mut cpu_set = CpuSet::new();
+    for cpu in request.cpus {
+        cpu_set
+            .set(cpu)
+            .map_err(|_| ShiitakeError::BadRequest(format!("Cpu {} is out of range", cpu)))?;
+    }
+    let pid = check_protected(pid)?;
+    nix::sched::sched_setaffinity(pid, &cpu_set)?;
+    Ok("Cpu affinity set")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_always_protected() {
+        let protected = ProtectedProcesses { names: Vec::new() };
+        assert!(protected.is_protected(1));
+        assert!(protected.is_protected(std::process::id()));
+    }
+
+    #[test]
+    fn test_invalid_pid() {
+        assert!(matches!(check_protected(0), Err(ShiitakeError::BadRequest(_))));
+        assert!(matches!(check_protected(u32::MAX), Err(ShiitakeError::BadRequest(_))));
+        assert!(matches!(check_protected(i32::MAX as u32 + 1), Err(ShiitakeError::BadRequest(_))));
+        //pid_max is at most 2^22
+        assert!(matches!(check_protected(i32::MAX as u32), Err(ShiitakeError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_parse_sched_policy() {
+        assert_eq!(parse_sched_policy("SCHED_FIFO"), Some(nix::libc::SCHED_FIFO));
+        assert_eq!(parse_sched_policy("rr"), Some(nix::libc::SCHED_RR));
+        assert_eq!(parse_sched_policy("deadline"), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Server side filtering, sorting and paging of the process list so clients don't download and re-sort every process.
+
+use std::collections::HashMap;
+
+use regex_lite::Regex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::procfs;
+use crate::types::{Process, Processes};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Cpu,
+    Memory,
+    Pid,
+    Name,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ProcessQuery {
+    ///pid when not set, so paging doesn't depend on the order processes were read in
+    pub sort: Option<SortKey>,
+    ///defaults to descending for cpu and memory, ascending otherwise
+    pub order: Option<SortOrder>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+    ///regex matched against the process name
+    pub name: Option<String>,
+    ///regex matched against the owning user's name
+    pub user: Option<String>,
+    pub min_cpu: Option<f64>,
+    pub min_memory: Option<u64>,
+}
+
+#[derive(Debug, Error)]
+pub enum ProcessQueryError {
+    #[error("Invalid regex: {0}")]
+    InvalidRegex(#[from] regex_lite::Error),
+}
+
+fn user_of(pid: u32, users: &mut HashMap<u32, Option<String>>) -> Option<String> {
+    use nix::unistd::{Uid, User};
+
+    let uid = procfs::parse_pid_status(&std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?).uid;
+    users
+        .entry(uid)
+        .or_insert_with(|| {
+            User::from_uid(Uid::from_raw(uid))
+                .ok()
+                .flatten()
+                .map(|user| user.name)
+        })
+        .clone()
+}
+
+pub fn apply_process_query(processes: Processes, query: &ProcessQuery) -> Result<Processes, ProcessQueryError> {
+    let name = query
+        .name
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .map_err(ProcessQueryError::InvalidRegex)?;
+    let user = query
+        .user
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .map_err(ProcessQueryError::InvalidRegex)?;
+    //several processes usually share a user so only look each uid up once
+    let mut users = HashMap::new();
+
+    let mut processes = processes
+        .into_iter()
+        .filter(|process| query.min_cpu.is_none_or(|min| process.cpu_usage >= min))
+        .filter(|process| query.min_memory.is_none_or(|min| process.memory_usage >= min))
+        .filter(|process| name.as_ref().is_none_or(|name| name.is_match(&process.name)))
+        .filter(|process| {
+            user.as_ref().is_none_or(|user| {
+                user_of(process.pid, &mut users).is_some_and(|name| user.is_match(&name))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let sort = query.sort.unwrap_or(SortKey::Pid);
+    //ties are broken by pid for the same reason
+    let compare = |a: &Process, b: &Process| {
+        match sort {
+            SortKey::Cpu => a.cpu_usage.total_cmp(&b.cpu_usage),
+            SortKey::Memory => a.memory_usage.cmp(&b.memory_usage),
+            SortKey::Pid => a.pid.cmp(&b.pid),
+            SortKey::Name => a.name.cmp(&b.name),
+        }
+        .then_with(|| a.pid.cmp(&b.pid))
+    };
+    let order = query.order.unwrap_or(match sort {
+        SortKey::Cpu | SortKey::Memory => SortOrder::Desc,
+        SortKey::Pid | SortKey::Name => SortOrder::Asc,
+    });
+    match order {
+        SortOrder::Asc => processes.sort_by(compare),
+        SortOrder::Desc => processes.sort_by(|a, b| compare(b, a)),
+    }
+
+    Ok(processes
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn processes() -> Processes {
+        vec![
+            Process { pid: 1, name: "init".to_string(), cpu_usage: 0.1, memory_usage: 1000 },
+            Process { pid: 30, name: "java".to_string(), cpu_usage: 42.0, memory_usage: 90000 },
+            Process { pid: 12, name: "photonvision".to_string(), cpu_usage: 20.0, memory_usage: 50000 },
+            Process { pid: 7, name: "journald".to_string(), cpu_usage: 0.5, memory_usage: 3000 },
+        ]
+    }
+
+    #[test]
+    fn test_pages_without_sort() {
+        let page = |offset| {
+            let query = ProcessQuery {
+                limit: Some(2),
+                offset,
+                ..Default::default()
+            };
+            apply_process_query(processes(), &query)
+                .unwrap()
+                .iter()
+                .map(|process| process.pid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(page(0), vec![1, 7]);
+        assert_eq!(page(2), vec![12, 30]);
+        assert!(page(4).is_empty());
+    }
+
+    #[test]
+    fn test_top_cpu() {
+        let query = ProcessQuery {
+            sort: Some(SortKey::Cpu),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let top = apply_process_query(processes(), &query).unwrap();
+        assert_eq!(top.iter().map(|process| process.pid).collect::<Vec<_>>(), vec![30, 12]);
+    }
+
+    #[test]
+    fn test_filters_and_paging() {
+        let query = ProcessQuery {
+            sort: Some(SortKey::Pid),
+            name: Some("^j".to_string()),
+            offset: 1,
+            ..Default::default()
+        };
+        let filtered = apply_process_query(processes(), &query).unwrap();
+        assert_eq!(filtered.iter().map(|process| process.pid).collect::<Vec<_>>(), vec![30]);
+
+        let query = ProcessQuery {
+            min_memory: Some(10000),
+            sort: Some(SortKey::Name),
+            order: Some(SortOrder::Desc),
+            ..Default::default()
+        };
+        let filtered = apply_process_query(processes(), &query).unwrap();
+        assert_eq!(filtered.iter().map(|process| process.pid).collect::<Vec<_>>(), vec![12, 30]);
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let query = ProcessQuery {
+            name: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(apply_process_query(processes(), &query).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+//! Parsers for the `/proc` files sysinfo doesn't expose (or exposes lossily).
+//! Each parser takes the file contents so it can be tested against fixtures.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+
+use crate::types::{MemoryStats, Pressure, PressureAverages};
+use crate::ShiitakeError;
+
+pub fn read_proc_file(path: &str) -> Result<String, ShiitakeError> {
+    Ok(std::fs::read_to_string(PathBuf::from(path))?)
+}
+
+///cumulative counters for one interface from `/proc/net/dev`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NetDevCounters {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_errors: u64,
+    pub tx_dropped: u64,
+}
+
+pub fn parse_net_dev(file: &str) -> Vec<(String, NetDevCounters)> {
+    let mut interfaces = Vec::new();
+    //the first two lines are the table header
+    for line in file.lines().skip(2) {
+        let Some((name, counters)) = line.split_once(':') else {
+            continue;
+        };
+        let fields = counters
+            .split_whitespace()
+            .map(|field| field.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>();
+        if fields.len() < 12 {
+            continue;
+        }
+        interfaces.push((
+            name.trim().to_string(),
+            NetDevCounters {
+                rx_bytes: fields[0],
+                rx_packets: fields[1],
+                rx_errors: fields[2],
+                rx_dropped: fields[3],
+                tx_bytes: fields[8],
+                tx_packets: fields[9],
+                tx_errors: fields[10],
+                tx_dropped: fields[11],
+            },
+        ));
+    }
+    interfaces
+}
+
+///cumulative counters for one block device from `/proc/diskstats`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DiskStatCounters {
+    pub reads_completed: u64,
+    pub sectors_read: u64,
+    pub writes_completed: u64,
+    pub sectors_written: u64,
+}
+
+///the kernel always reports diskstats in 512 byte sectors regardless of the device
+pub const DISKSTAT_SECTOR_SIZE: u64 = 512;
+
+pub fn parse_diskstats(file: &str) -> Vec<(String, DiskStatCounters)> {
+    let mut devices = Vec::new();
+    for line in file.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() < 10 {
+            continue;
+        }
+        let name = fields[2];
+        //virtual devices only add noise
+        if name.starts_with("loop") || name.starts_with("ram") {
+            continue;
+        }
+        let field = |i: usize| fields[i].parse::<u64>().unwrap_or(0);
+        devices.push((
+            name.to_string(),
+            DiskStatCounters {
+                reads_completed: field(3),
+                sectors_read: field(5),
+                writes_completed: field(7),
+                sectors_written: field(9),
+            },
+        ));
+    }
+    devices
+}
+
+///the 1, 5 and 15 minute load averages from `/proc/loadavg`
+pub fn parse_loadavg(file: &str) -> Option<(f64, f64, f64)> {
+    let mut fields = file.split_whitespace();
+    let mut next = || fields.next()?.parse::<f64>().ok();
+    Some((next()?, next()?, next()?))
+}
+
+///the system wide counters from `/proc/stat`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KernelCounters {
+    pub context_switches: u64,
+    pub interrupts: u64,
+    pub procs_running: u64,
+    pub procs_blocked: u64,
+    ///seconds since the unix epoch
+    pub boot_time: u64,
+}
+
+pub fn parse_stat(file: &str) -> KernelCounters {
+    let mut counters = KernelCounters::default();
+    for line in file.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(key), Some(value)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let value = value.parse::<u64>().unwrap_or(0);
+        match key {
+            "ctxt" => counters.context_switches = value,
+            //the first number on the intr line is the total across all irqs
+            "intr" => counters.interrupts = value,
+            "procs_running" => counters.procs_running = value,
+            "procs_blocked" => counters.procs_blocked = value,
+            "btime" => counters.boot_time = value,
+            _ => {}
+        }
+    }
+    counters
+}
+
+///seconds since boot from `/proc/uptime`
+pub fn parse_uptime(file: &str) -> Option<f64> {
+    file.split_whitespace().next()?.parse().ok()
+}
+
+///the fields of `/proc/<pid>/stat` the process detail view needs, times are in clock ticks
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PidStat {
+    pub comm: String,
+    pub state: char,
+    pub ppid: u32,
+    pub utime: u64,
+    pub stime: u64,
+    pub nice: i32,
+    pub num_threads: u64,
+    pub start_time: u64,
+    pub rt_priority: u32,
+    pub policy: u32,
+}
+
+pub fn parse_pid_stat(file: &str) -> Option<PidStat> {
+    //comm can contain spaces and parentheses so split around the outermost pair
+    let comm_start = file.find('(')?;
+    let comm_end = file.rfind(')')?;
+    let comm = file.get(comm_start + 1..comm_end)?.to_string();
+    //field 3 (state) is index 0 here
+    let fields = file.get(comm_end + 1..)?.split_whitespace().collect::<Vec<_>>();
+    let field = |n: usize| fields.get(n - 3).and_then(|value| value.parse::<u64>().ok());
+    Some(PidStat {
+        comm,
+        state: fields.first()?.chars().next()?,
+        ppid: field(4)? as u32,
+        utime: field(14)?,
+        stime: field(15)?,
+        nice: fields.get(19 - 3)?.parse().ok()?,
+        num_threads: field(20)?,
+        start_time: field(22)?,
+        rt_priority: field(40).unwrap_or(0) as u32,
+        policy: field(41).unwrap_or(0) as u32,
+    })
+}
+
+///the fields of `/proc/<pid>/status` not already covered by `stat`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PidStatus {
+    pub uid: u32,
+    pub cpus_allowed: Vec<u32>,
+}
+
+pub fn parse_pid_status(file: &str) -> PidStatus {
+    let mut status = PidStatus::default();
+    for line in file.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key {
+            //real, effective, saved, filesystem
+            "Uid" => {
+                status.uid = value
+                    .split_whitespace()
+                    .next()
+                    .and_then(|uid| uid.parse().ok())
+                    .unwrap_or(0)
+            }
+            "Cpus_allowed_list" => status.cpus_allowed = parse_cpu_list(value.trim()),
+            _ => {}
+        }
+    }
+    status
+}
+
+///expands a kernel cpu list like `0-2,5` into `[0, 1, 2, 5]`
+pub fn parse_cpu_list(list: &str) -> Vec<u32> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                if let (Ok(start), Ok(end)) = (start.parse::<u32>(), end.parse::<u32>()) {
+                    cpus.extend(start..=end);
+                }
+            }
+            None => {
+                if let Ok(cpu) = range.parse() {
+                    cpus.push(cpu);
+                }
+            }
+        }
+    }
+    cpus
+}
+
+///`/proc/<pid>/statm` sizes in pages: (virtual, resident, shared)
+pub fn parse_pid_statm(file: &str) -> Option<(u64, u64, u64)> {
+    let mut fields = file.split_whitespace();
+    let mut next = || fields.next()?.parse::<u64>().ok();
+    Some((next()?, next()?, next()?))
+}
+
+///`/proc/meminfo` values are in kibibytes even though the file says kB
+pub fn parse_meminfo(file: &str) -> MemoryStats {
+    let mut memory = MemoryStats::default();
+    let mut swap_free = 0;
+    for line in file.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(value) = value.split_whitespace().next().and_then(|value| value.parse::<u64>().ok()) else {
+            continue;
+        };
+        let bytes = value * 1024;
+        match key {
+            "MemTotal" => memory.total = bytes,
+            "MemAvailable" => memory.available = bytes,
+            "MemFree" => memory.free = bytes,
+            "Buffers" => memory.buffers = bytes,
+            "Cached" => memory.cached = bytes,
+            "Shmem" => memory.shmem = bytes,
+            "SwapTotal" => memory.swap_total = bytes,
+            "SwapFree" => swap_free = bytes,
+            "Slab" => memory.slab = bytes,
+            _ => {}
+        }
+    }
+    memory.swap_used = memory.swap_total.saturating_sub(swap_free);
+    memory
+}
+
+fn parse_pressure_averages(line: &str) -> Option<PressureAverages> {
+    let mut averages = PressureAverages::default();
+    for field in line.split_whitespace().skip(1) {
+        let (key, value) = field.split_once('=')?;
+        match key {
+            "avg10" => averages.avg10 = value.parse().ok()?,
+            "avg60" => averages.avg60 = value.parse().ok()?,
+            "avg300" => averages.avg300 = value.parse().ok()?,
+            "total" => averages.total = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    Some(averages)
+}
+
+///parses one of the `/proc/pressure/*` pressure stall information files
+pub fn parse_pressure(file: &str) -> Option<Pressure> {
+    let mut some = None;
+    let mut full = None;
+    for line in file.lines() {
+        if line.starts_with("some ") {
+            some = parse_pressure_averages(line);
+        } else if line.starts_with("full ") {
+            full = parse_pressure_averages(line);
+        }
+    }
+    Some(Pressure { some: some?, full })
+}
+
+///`/proc/net/tcp` state of an open connection
+const TCP_ESTABLISHED: &str = "01";
+
+///an address and port from `/proc/net/tcp` or `/proc/net/tcp6`, the address is in host byte order 32 bits at a time
+fn parse_socket_address(field: &str) -> Option<(IpAddr, u16)> {
+    let (address, port) = field.split_once(':')?;
+    let words = (0..address.len())
+        .step_by(8)
+        .map(|i| u32::from_str_radix(address.get(i..i + 8)?, 16).ok().map(u32::from_be))
+        .collect::<Option<Vec<_>>>()?;
+    let address = match words[..] {
+        [v4] => IpAddr::V4(Ipv4Addr::from(v4)),
+        [a, b, c, d] => IpAddr::V6(Ipv6Addr::from(
+            (u128::from(a) << 96) | (u128::from(b) << 64) | (u128::from(c) << 32) | u128::from(d),
+        )),
+        _ => return None,
+    };
+    Some((address, u16::from_str_radix(port, 16).ok()?))
+}
+
+///the remote addresses of established connections to `local_port` in `/proc/net/tcp` or `/proc/net/tcp6`
+pub fn parse_tcp_peers(file: &str, local_port: u16) -> Vec<IpAddr> {
+    //the first line is the table header
+    file.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let (_, port) = parse_socket_address(fields.get(1)?)?;
+            let (remote, _) = parse_socket_address(fields.get(2)?)?;
+            (port == local_port && *fields.get(3)? == TCP_ESTABLISHED).then_some(remote)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NET_DEV: &str = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  123456     789    0    0    0     0          0         0   123456     789    0    0    0     0       0          0
+  eth0: 9876543   12345    3    7    0     0          0        12  1234567    6789    1    2    0     0       0          0
+";
+
+    const DISKSTATS: &str = "   7       0 loop0 10 0 20 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+ 179       0 mmcblk0 4000 100 80000 1500 2000 300 40000 9000 0 5000 10500 0 0 0 0 0 0
+ 179       1 mmcblk0p1 300 0 6000 100 10 0 80 20 0 100 120 0 0 0 0 0 0
+";
+
+    const MEMINFO: &str = "MemTotal:         506568 kB
+MemFree:          112340 kB
+MemAvailable:     301234 kB
+Buffers:           10240 kB
+Cached:           180000 kB
+SwapCached:            0 kB
+SwapTotal:        102400 kB
+SwapFree:          51200 kB
+Shmem:              4096 kB
+Slab:              20480 kB
+";
+
+    #[test]
+    fn test_parse_meminfo() {
+        let memory = parse_meminfo(MEMINFO);
+        assert_eq!(memory.total, 506568 * 1024);
+        assert_eq!(memory.available, 301234 * 1024);
+        assert_eq!(memory.cached, 180000 * 1024);
+        assert_eq!(memory.swap_used, 51200 * 1024);
+        assert_eq!(memory.slab, 20480 * 1024);
+        assert_eq!(memory.pressure, None);
+    }
+
+    #[test]
+    fn test_parse_pressure() {
+        let pressure = parse_pressure(
+            "some avg10=1.50 avg60=0.75 avg300=0.10 total=123456\nfull avg10=0.50 avg60=0.25 avg300=0.00 total=6543\n",
+        )
+        .unwrap();
+        assert_eq!(pressure.some.avg10, 1.5);
+        assert_eq!(pressure.some.total, 123456);
+        assert_eq!(pressure.full.unwrap().avg60, 0.25);
+        assert_eq!(parse_pressure(""), None);
+    }
+
+    #[test]
+    fn test_parse_loadavg_and_stat() {
+        assert_eq!(parse_loadavg("0.18 0.32 0.19 2/72 6298\n"), Some((0.18, 0.32, 0.19)));
+        let counters = parse_stat(
+            "cpu  20528 0 2576 92328 274 0 3 1041 0 0\nintr 113819 0 0 1\nctxt 253774\nbtime 1792298663\nprocs_running 2\nprocs_blocked 1\n",
+        );
+        assert_eq!(
+            counters,
+            KernelCounters {
+                context_switches: 253774,
+                interrupts: 113819,
+                procs_running: 2,
+                procs_blocked: 1,
+                boot_time: 1792298663,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_pid_files() {
+        let stat = parse_pid_stat(
+            "1234 (frc (robot) main) S 1 1234 1234 0 -1 4194560 5000 0 0 0 1500 300 0 0 -20 -5 42 0 98765 2000000000 50000 18446744073709551615 1 1 0 0 0 0 0 4096 0 0 0 0 17 1 50 1 0 0 0 0 0 0 0 0 0 0 0",
+        )
+        .unwrap();
+        assert_eq!(stat.comm, "frc (robot) main");
+        assert_eq!(stat.state, 'S');
+        assert_eq!(stat.ppid, 1);
+        assert_eq!(stat.utime, 1500);
+        assert_eq!(stat.stime, 300);
+        assert_eq!(stat.nice, -5);
+        assert_eq!(stat.num_threads, 42);
+        assert_eq!(stat.start_time, 98765);
+        assert_eq!(stat.rt_priority, 50);
+        assert_eq!(stat.policy, 1);
+
+        let status = parse_pid_status("Name:\tjava\nUid:\t500\t500\t500\t500\nCpus_allowed_list:\t0-1,3\n");
+        assert_eq!(status.uid, 500);
+        assert_eq!(status.cpus_allowed, vec![0, 1, 3]);
+
+        assert_eq!(parse_uptime("350735.47 234388.90\n"), Some(350735.47));
+        assert_eq!(parse_pid_statm("5000 1200 300 10 0 800 0\n"), Some((5000, 1200, 300)));
+    }
+
+    #[test]
+    fn test_parse_net_dev() {
+        let interfaces = parse_net_dev(NET_DEV);
+        assert_eq!(interfaces.len(), 2);
+        assert_eq!(interfaces[1].0, "eth0");
+        assert_eq!(
+            interfaces[1].1,
+            NetDevCounters {
+                rx_bytes: 9876543,
+                rx_packets: 12345,
+                rx_errors: 3,
+                rx_dropped: 7,
+                tx_bytes: 1234567,
+                tx_packets: 6789,
+                tx_errors: 1,
+                tx_dropped: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_tcp_peers() {
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:06CC 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1 1 0000000000000000 100 0 0 10 0
+   1: 0240400A:06CC 0540400A:D431 01 00000000:00000000 02:000A7D24 00000000     0        0 2 4 0000000000000000 20 4 30 10 -1
+   2: 0240400A:0050 0540400A:D432 01 00000000:00000000 02:000A7D24 00000000     0        0 3 4 0000000000000000 20 4 30 10 -1
+";
+        assert_eq!(parse_tcp_peers(tcp, 1740), vec![IpAddr::V4(Ipv4Addr::new(10, 64, 64, 5))]);
+        let tcp6 = "  sl  local_address                         remote_address                        st
+   0: 000080FE0000000000FEFF0202000000:06CC 000080FE0000000000FEFF0205000000:D431 01
+";
+        assert_eq!(parse_tcp_peers(tcp6, 1740), vec!["fe80::2ff:fe00:0:5".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn test_parse_diskstats() {
+        let devices = parse_diskstats(DISKSTATS);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].0, "mmcblk0");
+        assert_eq!(devices[0].1.reads_completed, 4000);
+        assert_eq!(devices[0].1.sectors_read, 80000);
+        assert_eq!(devices[0].1.writes_completed, 2000);
+        assert_eq!(devices[0].1.sectors_written, 40000);
+    }
+}
@@ -1,9 +1,10 @@
 #![allow(dead_code)]
 
-use std::{path::PathBuf, io::Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
 use axum::routing::{get, post};
 
-use axum::http::StatusCode;
+use crate::{ShiitakeError, ShiitakeResult};
 
 macro_rules! error {
     ($($arg:tt)*) => {
@@ -12,25 +13,17 @@ macro_rules! error {
     }
 }
 
+///the serial of the first device listed, which is the roboRIO itself
 fn get_serial(path: PathBuf) -> Result<String, RioDataError> {
     let file = std::fs::read_to_string(path)?;
     const PREFIX: &str = "Serial=";
     const SERIAL_SIZE: usize = 16;
 
-    let mut serial = "0000000000000000";
-
-    //pretend this is a regex :3
-    let lines = file.lines().collect::<Vec<&str>>();
-    for line in lines {
-        if line.contains(PREFIX) {
-            for i in 0..(line.len() - PREFIX.len()) {
-                if line[i..].starts_with(PREFIX) {
-                    serial = &line[i + PREFIX.len()..i + PREFIX.len() + SERIAL_SIZE];
-                    break;
-                }
-            }
-        }
-    }
+    let serial = file
+        .lines()
+        .filter_map(|line| line.split_once(PREFIX))
+        .find_map(|(_, rest)| rest.get(..SERIAL_SIZE))
+        .unwrap_or("0000000000000000");
 
     let mut serial = serial.to_string();
     while serial.starts_with('0') {
@@ -40,73 +33,29 @@ fn get_serial(path: PathBuf) -> Result<String, RioDataError> {
     Ok(serial)
 }
 
-fn fix_comment_hex(hex_str: String) -> String {
-    let mut new_str = String::new();
-    for char in hex_str.chars() {
-        let orded = char as u8;
-        if 58 <= orded && orded <= 63 {
-            new_str.push((orded + 39) as char);
-        } else {
-            new_str.push(char);
-        }
-    }
-    new_str
-}
-fn unfix_comment_hex(hex_str: String) -> String {
-    let mut new_str = String::new();
-    for char in hex_str.chars() {
-        let orded = char as u8;
-        if 97 <= orded && orded <= 102 {
-            new_str.push((orded - 39) as char);
-        } else {
-            new_str.push(char);
-        }
-    }
-    new_str
-}
-fn swap_hex(hex_str: String) -> String {
-    format!("{}{}", &hex_str[1..], &hex_str[..1])
-}
-fn comment_hex_to_int(hex_str: String) -> i64 {
-    i64::from_str_radix(&swap_hex(fix_comment_hex(hex_str)), 16)
-        .expect("Failed to convert hex to int")
-}
-
-fn decode_comment(encoded_str: String) -> String {
-    let mut decoded_str = String::new();
-    for i in (0..encoded_str.len()).step_by(2) {
-        let hex_str = &encoded_str[i..i+2];
-        let int_val = comment_hex_to_int(hex_str.to_string());
-        let ascii_val = int_val as u8 as char;
-        decoded_str.push(ascii_val);
-    }
-    decoded_str
-}
-
-fn encode_comment(decoded_str: String) -> String {
-    let mut encoded_str = String::new();
-    for char in decoded_str.chars() {
-        let int_val = char as u8;
-        let hex_str = format!("{:x}", int_val);
-        encoded_str.push_str(&unfix_comment_hex(swap_hex(hex_str)));
-    }
-    encoded_str
-}
-
 fn set_hostname(hostname: String) -> Result<(), RioDataError> {
-    let mut file = std::fs::File::create(PathBuf::from("/etc/hostname"))?;
-    file.write_all(hostname.as_bytes())?;
+    crate::backups::write_config_file(&crate::config::get().rio.path(HOSTNAME), hostname.as_bytes())?;
     Ok(())
 }
 
 use axum::Router;
-use serde_json::{Map, Value};
+use serde_json::{json, Map, Value};
 use static_init::dynamic;
-use crate::ini::{read_ini, read_ini_field, Ini, IniSection, IniTypes};
+use crate::ini::{read_ini, read_ini_field, Ini};
+use crate::network::{prefix_to_mask, InterfaceConfig};
+use crate::rio_schema::{ETH0, SYSTEM_SETTINGS};
 use crate::routes;
 
 type RioDataError = crate::ini::IniError;
 
+//paths of the files NI's tools keep the Rio's configuration in, relative to `RioConfig::root`
+pub const NI_RT_INI: &str = "etc/natinst/share/ni-rt.ini";
+pub const ATOMICZYNQ_CONFIG: &str = "var/lib/compactrio/atomiczynq.config";
+pub const IMAGE_METADATA_INI: &str = "etc/natinst/share/scs_imagemetadata.ini";
+pub const HOSTNAME: &str = "etc/hostname";
+///the command frcRunRobot.sh runs, written by GradleRIO on every deploy
+pub const ROBOT_COMMAND: &str = "home/lvuser/robotCommand";
+
 #[derive(Debug)]
 struct RioData {
     serial: String,
@@ -115,193 +64,387 @@ struct RioData {
 }
 
 impl RioData {
-    fn new() -> Result<Self, RioDataError>{
+    fn new(root: &Path) -> Result<Self, RioDataError>{
         Ok(RioData {
-            serial: get_serial(PathBuf::from("/var/lib/compactrio/atomiczynq.config"))?,
-            rt_ini: read_ini(PathBuf::from("/etc/natinst/share/ni-rt.ini"))?,
+            serial: get_serial(root.join(ATOMICZYNQ_CONFIG))?,
+            rt_ini: read_ini(root.join(NI_RT_INI))?,
             image_version: read_ini_field(
-                PathBuf::from("/etc/natinst/share/scs_imagemetadata.ini"),
+                root.join(IMAGE_METADATA_INI),
                 "ImageMetadata",
                 "IMAGEVERSION",
-            )?.to_string()
+            )?.into_string()
             .ok_or(RioDataError::CustomError("Failed to convert image version to string"))?,
         })
     }
 
-    fn to_json_map(&self) -> Result<Map<String, Value>, RioDataError> {
+    fn to_json_map(&self) -> Map<String, Value> {
         let mut map = Map::new();
         map.insert("serial".into(), Value::String(self.serial.clone()));
         map.insert("image_version".into(), Value::String(self.image_version.clone()));
-
-        let system_settings = self.rt_ini
-            .get("systemsettings")
-            .ok_or(RioDataError::SectionDoesntExist)?;
-
-        map.insert("no_fpga_app".into(), Value::Bool(system_settings
-            .get("NoFPGAApp.enabled")
-            .ok_or(RioDataError::KeyDoesntExist)?
-            .clone().to_boolean_from_string()
-            .ok_or(RioDataError::CustomError("Failed to convert NoFPGAApp.enabled to string"))?)
-        );
-        map.insert("console_out".into(), Value::Bool(system_settings
-            .get("ConsoleOut.enabled")
-            .ok_or(RioDataError::KeyDoesntExist)?
-            .clone().to_boolean_from_string()
-            .ok_or(RioDataError::CustomError("Failed to convert ConsoleOut.enabled to string"))?)
-        );
-        map.insert("no_app".into(), Value::Bool(system_settings
-            .get("NoApp.enabled")
-            .ok_or(RioDataError::KeyDoesntExist)?
-            .clone().to_boolean_from_string()
-            .ok_or(RioDataError::CustomError("Failed to convert NoApp.enabled to string"))?)
-        );
-        map.insert("safe_mode".into(), Value::Bool(system_settings
-            .get("SafeMode.enabled")
-            .ok_or(RioDataError::KeyDoesntExist)?
-            .clone().to_boolean_from_string()
-            .ok_or(RioDataError::CustomError("Failed to convert SafeMode.enabled to string"))?)
-        );
-        map.insert("host_name".into(), Value::String(system_settings
-            .get("host_name")
-            .ok_or(RioDataError::KeyDoesntExist)?
-            .clone().to_string()
-            .ok_or(RioDataError::CustomError("Failed to convert host_name to string"))?)
-        );
-        map.insert("comment".into(), Value::String(decode_comment(system_settings
-            .get("Comment")
-            .ok_or(RioDataError::KeyDoesntExist)?
-            .clone().to_string()
-            .ok_or(RioDataError::CustomError("Failed to convert Comment to string"))?))
-        );
-
-        Ok(map)
+        map.extend(SYSTEM_SETTINGS.read(&self.rt_ini));
+        map
     }
 }
 
-#[dynamic(lazy)]
-static mut RIO_DATA: RioData = match RioData::new() {
-    Ok(rio_data) => rio_data,
-    Err(_e) => {
-        error!("Failed to get rio data: {:?}", _e);
-        panic!("Failed to get rio data");
-    }
-};
+///`None` until `load_rio_data` succeeds
+#[dynamic]
+static mut RIO_DATA: Option<RioData> = None;
+
+const NOT_LOADED: RioDataError = RioDataError::CustomError("Rio data hasn't been loaded");
+
+///reads the Rio's config files from under `root`, has to succeed before any Rio route is used
+pub fn load_rio_data(root: &Path) -> Result<(), RioDataError> {
+    let rio_data = RioData::new(root).inspect_err(|_e| {
+        error!("Failed to get rio data from {}: {:?}", root.display(), _e);
+    })?;
+    *RIO_DATA.write() = Some(rio_data);
+    Ok(())
+}
 
 pub fn init_rio(app: Router) -> Router {
     app
     .route(routes::RIO, get(get_rio))
     .route(routes::RIO, post(set_rio))
-    .route("/nisysdetails/ping", get(|| async { "SHIITAKE" }))
+    .route(routes::RIO_USER_PROGRAM, get(get_user_program))
+    .route(routes::RIO_USER_PROGRAM_ACTION, post(user_program_action))
+    .route(routes::RIO_PING, get(|| async { "SHIITAKE" }))
+}
+
+async fn get_rio() -> ShiitakeResult<axum::Json<Map<String, Value>>> {
+    let rio_data = RIO_DATA.read();
+    let rio_data = rio_data.as_ref().ok_or(NOT_LOADED)?;
+    Ok(axum::Json(rio_data.to_json_map()))
 }
 
-async fn get_rio() -> Result<String, StatusCode> {
-    match RIO_DATA.read().to_json_map() {
-        Ok(map) => Ok(serde_json::to_string(&map).unwrap()),
-        Err(_e) => {
-            error!("Failed to get rio data: {:?}", _e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+async fn set_rio(map_str: String) -> ShiitakeResult<()> {
+    let map: Map<String, Value> = serde_json::from_str(&map_str)
+        .map_err(|e| ShiitakeError::BadRequest(format!("Invalid json: {}", e)))?;
+    let mut rio_data = RIO_DATA.write();
+    let rio_data = rio_data.as_mut().ok_or(NOT_LOADED)?;
+    //the loaded ini only changes once everything is on disk, so a failed write can't leave it ahead of the files
+    let mut rt_ini = rio_data.rt_ini.clone();
+    SYSTEM_SETTINGS.write(&mut rt_ini, &map)?;
+    rt_ini.save()?;
+    if let Some(Value::String(name)) = map.get("host_name") {
+        if let Err(e) = set_hostname(name.clone()) {
+            let _ = rio_data.rt_ini.save();
+            return Err(e.into());
         }
     }
+    rio_data.rt_ini = rt_ini;
+    Ok(())
 }
 
-fn boolc(b: bool) -> String {
-    if b {
-        "True".to_string()
-    } else {
-        "False".to_string()
+///what eth0 comes up as on boot, `None` if ni-rt.ini has no usable eth0 section
+pub fn read_eth0() -> Option<InterfaceConfig> {
+    let rio_data = RIO_DATA.read();
+    let eth0 = ETH0.read(&rio_data.as_ref()?.rt_ini);
+    let enabled = |key: &str| eth0[key].as_bool().unwrap_or(false);
+    let ip = |key: &str| eth0[key].as_str().and_then(|ip| ip.parse::<Ipv4Addr>().ok());
+    if enabled("dhcp_enabled") {
+        return Some(InterfaceConfig::Dhcp);
+    }
+    match ip("ip_address") {
+        Some(address) => Some(InterfaceConfig::Static {
+            address,
+            prefix: u32::from(ip("subnet_mask")?).count_ones() as u8,
+            gateway: ip("gateway"),
+            dns: ip("dns_address").into_iter().map(IpAddr::V4).collect(),
+        }),
+        None if enabled("link_local_enabled") => Some(InterfaceConfig::LinkLocal),
+        None => None,
     }
 }
 
-async fn set_rio(map_str: String) -> Result<(), StatusCode> {
-    let map: Map<String, Value> = serde_json::from_str(&map_str)
-        .map_err(|_| {error!("Failed to parse json"); StatusCode::BAD_REQUEST})?;
-    let mut rio_data = RIO_DATA.write();
-    let system_settings = rio_data.rt_ini
-        .get_mut("systemsettings")
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-    for (key, value) in map {
-        match key.as_str() {
-            "no_fpga_app" => system_settings.set(
-                "NoFPGAApp.enabled",
-                IniTypes::Boolean(value.as_bool().ok_or(StatusCode::BAD_REQUEST)?)
-            ).map_err(|_| {error!("NoFPGAApp.enabled set err"); StatusCode::INTERNAL_SERVER_ERROR})?,
-            "console_out" => system_settings.set(
-                "ConsoleOut.enabled",
-                IniTypes::Boolean(value.as_bool().ok_or(StatusCode::BAD_REQUEST)?)
-            ).map_err(|_| {error!("ConsoleOut.enabled set err"); StatusCode::INTERNAL_SERVER_ERROR})?,
-            "no_app" => system_settings.set(
-                "NoApp.enabled",
-                IniTypes::String(boolc(value.as_bool().ok_or(StatusCode::BAD_REQUEST)?))
-            ).map_err(|_| {error!("NoApp.enabled set err"); StatusCode::INTERNAL_SERVER_ERROR})?,
-            "safe_mode" => system_settings.set(
-                "SafeMode.enabled", 
-                IniTypes::String(boolc(value.as_bool().ok_or(StatusCode::BAD_REQUEST)?))
-            ).map_err(|_| {error!("SafeMode.enabled set err"); StatusCode::INTERNAL_SERVER_ERROR})?,
-            "host_name" => {
-                let name = value.as_str().ok_or(StatusCode::BAD_REQUEST)?.to_string();
-                set_hostname(name.clone()).map_err(|_| {error!("host_name sfile write err"); StatusCode::INTERNAL_SERVER_ERROR})?;
-                system_settings.set(
-                    "host_name",
-                IniTypes::String(name)
-                ).map_err(|_| {error!("host_name set err"); StatusCode::INTERNAL_SERVER_ERROR})?
-            },
-            "comment" => system_settings.set(
-                "Comment",
-                IniTypes::String(encode_comment(value.as_str().ok_or(StatusCode::BAD_REQUEST)?.to_string()))
-            ).map_err(|_| {error!("Comment set err"); StatusCode::INTERNAL_SERVER_ERROR})?,
-            _ => return Err(StatusCode::BAD_REQUEST)
+///the keys only a static config uses, dropped when switching away from one so they can't come back on a later boot
+const ETH0_STATIC_KEYS: &[&str] = &["IP_Address", "Subnet_Mask", "Gateway", "DNS_Address"];
+
+///sets the keys the config uses in place, every other line of the section stays as it was
+fn set_eth0(ini: &mut Ini, config: &InterfaceConfig) -> ShiitakeResult<()> {
+    let mut values = Map::new();
+    values.insert("mode".into(), json!("TCPIP"));
+    values.insert("media_mode".into(), json!("Auto"));
+    match config {
+        InterfaceConfig::Dhcp => {
+            values.insert("dhcp_enabled".into(), json!(true));
+            values.insert("link_local_enabled".into(), json!(true));
+        }
+        InterfaceConfig::Static { address, prefix, gateway, dns } => {
+            values.insert("dhcp_enabled".into(), json!(false));
+            values.insert("link_local_enabled".into(), json!(false));
+            values.insert("ip_address".into(), json!(address.to_string()));
+            values.insert("subnet_mask".into(), json!(prefix_to_mask(*prefix).to_string()));
+            if let Some(gateway) = gateway {
+                values.insert("gateway".into(), json!(gateway.to_string()));
+            }
+            //ni-rt.ini only has room for one ipv4 dns server
+            if let Some(dns) = dns.iter().find(|dns| dns.is_ipv4()) {
+                values.insert("dns_address".into(), json!(dns.to_string()));
+            }
+        }
+        InterfaceConfig::LinkLocal => {
+            values.insert("dhcp_enabled".into(), json!(false));
+            values.insert("link_local_enabled".into(), json!(true));
         }
     }
-    rio_data.rt_ini.save()
-        .map_err(|_| {error!("ni-rt.ini save err"); StatusCode::INTERNAL_SERVER_ERROR})?;
+    ETH0.write(ini, &values)?;
+    if let Some(section) = ini.get_mut(ETH0.section) {
+        let unused = match config {
+            InterfaceConfig::Static { gateway, dns, .. } => {
+                let mut unused = Vec::new();
+                if gateway.is_none() {
+                    unused.push("Gateway");
+                }
+                if !dns.iter().any(|dns| dns.is_ipv4()) {
+                    unused.push("DNS_Address");
+                }
+                unused
+            }
+            _ => ETH0_STATIC_KEYS.to_vec(),
+        };
+        for key in unused {
+            section.remove(key);
+        }
+    }
+    Ok(())
+}
+
+///the Rio's daemons set eth0 up from ni-rt.ini on boot
+pub fn write_eth0(config: &InterfaceConfig) -> ShiitakeResult<()> {
+    let mut rio_data = RIO_DATA.write();
+    let rio_data = rio_data.as_mut().ok_or(NOT_LOADED)?;
+    let mut rt_ini = rio_data.rt_ini.clone();
+    set_eth0(&mut rt_ini, config)?;
+    rt_ini.save()?;
+    rio_data.rt_ini = rt_ini;
     Ok(())
 }
 
-pub fn write_static_ip(ip: String, gateway: String, dns: String) {
-    let ini = &mut RIO_DATA.write().rt_ini;
-    let mut section = IniSection::new("eth0");
-    section.create_and_set("dhcpenabled", IniTypes::String("0".to_string()));
-    section.create_and_set("linklocalenabled", IniTypes::String("0".to_string()));
-    section.create_and_set("IP_Address", IniTypes::String(ip));
-    section.create_and_set("Subnet_Mask", IniTypes::String("255.255.255.0".to_string()));
-    section.create_and_set("Gateway", IniTypes::String(gateway));
-    section.create_and_set("DNS_Address", IniTypes::String(dns));
-    section.create_and_set("Mode", IniTypes::String("TCPIP".to_string()));
-    section.create_and_set("MediaMode", IniTypes::String("Auto".to_string()));
-    ini.create_and_set(section);
-    let _ = ini.save();
+///`-t` kills the running user program, `-r` then starts it again
+const FRC_KILL_ROBOT: &str = ". /etc/profile.d/natinst-path.sh; /usr/local/frc/bin/frcKillRobot.sh";
+
+#[derive(Debug, serde::Serialize)]
+struct UserProgramStatus {
+    running: bool,
+    pid: Option<u32>,
+    ///seconds since the user program started
+    uptime: Option<u64>,
+    ///how many times the server has seen the user program come back with a new pid
+    restart_count: u32,
+    ///NoApp.enabled in ni-rt.ini, the user program won't be started on boot while this is set
+    no_app: bool,
+    robot_command: Option<String>,
 }
 
-pub fn write_dhcp(ip: String) {
-    let ini = &mut RIO_DATA.write().rt_ini;
-    let mut section = IniSection::new("eth0");
-    section.create_and_set("dhcpenabled", IniTypes::String("1".to_string()));
-    section.create_and_set("linklocalenabled", IniTypes::String("1".to_string()));
-    section.create_and_set("Mode", IniTypes::String("TCPIP".to_string()));
-    section.create_and_set("MediaMode", IniTypes::String("Auto".to_string()));
-    section.create_and_set("dhcpipaddr", IniTypes::String(ip));
-    ini.create_and_set(section);
-    let _ = ini.save();
+#[derive(Debug, Default)]
+struct UserProgramTracker {
+    last_pid: Option<u32>,
+    restart_count: u32,
 }
 
+#[dynamic]
+static mut USER_PROGRAM_TRACKER: UserProgramTracker = UserProgramTracker::default();
+
+///splits a shell command on whitespace while keeping double quoted arguments together
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in command.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+    args
+}
 
+///the user program is the process running the robotCommand executable with its last argument (usually the jar)
+fn matches_robot_command(cmdline: &[String], command: &[String]) -> bool {
+    match (cmdline.first(), command.first(), command.last()) {
+        (Some(exe), Some(command_exe), Some(last)) => exe == command_exe && cmdline.contains(last),
+        _ => false,
+    }
+}
+
+fn find_user_program(command: &[String]) -> Option<u32> {
+    for entry in std::fs::read_dir("/proc").ok()?.filter_map(|entry| entry.ok()) {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let Ok(cmdline) = std::fs::read(entry.path().join("cmdline")) else {
+            continue;
+        };
+        let cmdline = cmdline
+            .split(|byte| *byte == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).to_string())
+            .collect::<Vec<_>>();
+        if matches_robot_command(&cmdline, command) {
+            return Some(pid);
+        }
+    }
+    None
+}
+
+fn user_program_status() -> UserProgramStatus {
+    let robot_command = std::fs::read_to_string(crate::config::get().rio.path(ROBOT_COMMAND)).ok();
+    let pid = robot_command
+        .as_deref()
+        .and_then(|command| find_user_program(&split_command(command)));
+
+    let mut tracker = USER_PROGRAM_TRACKER.write();
+    if let (Some(last_pid), Some(pid)) = (tracker.last_pid, pid) {
+        if last_pid != pid {
+            tracker.restart_count += 1;
+        }
+    }
+    if pid.is_some() {
+        tracker.last_pid = pid;
+    }
+
+    let uptime = pid
+        .and_then(|pid| crate::measuring::measure_process_detail(pid).ok())
+        .map(|detail| crate::history::now_millis().saturating_sub(detail.start_time) / 1000);
+    let no_app = RIO_DATA
+        .read()
+        .as_ref()
+        .and_then(|rio_data| SYSTEM_SETTINGS.read_field(&rio_data.rt_ini, "no_app"))
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+
+    UserProgramStatus {
+        running: pid.is_some(),
+        pid,
+        uptime,
+        restart_count: tracker.restart_count,
+        no_app,
+        robot_command: robot_command.map(|command| command.trim().to_string()),
+    }
+}
+
+async fn get_user_program() -> axum::Json<UserProgramStatus> {
+    axum::Json(user_program_status())
+}
+
+///the frcKillRobot.sh flags for an action, `-t` kills the running program and `-r` has the daemon start it again
+fn action_flags(action: &str, running: bool) -> ShiitakeResult<(&'static str, &'static str)> {
+    match action {
+        "start" if running => Err(ShiitakeError::Conflict("The user program is already running".to_string())),
+        "start" => Ok(("-r", "User program starting")),
+        "restart" => Ok(("-t -r", "User program restarting")),
+        "stop" => Ok(("-t", "User program stopped")),
+        _ => Err(ShiitakeError::NotFound(format!("Unknown action {}", action))),
+    }
+}
+
+async fn user_program_action(axum::extract::Path(action): axum::extract::Path<String>) -> ShiitakeResult<&'static str> {
+    let (flags, message) = action_flags(&action, user_program_status().running)?;
+    let status = std::process::Command::new("sh")
+        .args(["-c", &format!("{} {}", FRC_KILL_ROBOT, flags)])
+        .status()?;
+    if !status.success() {
+        return Err(ShiitakeError::CommandFailed(format!("frcKillRobot.sh {} exited with {}", flags, status)));
+    }
+    Ok(message)
+}
 
 
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use crate::ini::IniTypes;
+
+    fn fixture_root() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/rio")
+    }
 
     #[test]
     fn test_read_ini() {
-        let ini = read_ini(PathBuf::from("test.ini")).unwrap();
-        assert_eq!(ini.get("Default").unwrap().get("Port").unwrap(), &IniTypes::Integer(80));
-        assert_eq!(ini.get("Default").unwrap().get("SSLEnabled").unwrap(), &IniTypes::Boolean(false));
-        assert_eq!(ini.get("Hosts").unwrap().get("Default").unwrap(), &IniTypes::String("Default Host".to_string()));
+        let ini = read_ini(fixture_root().join(NI_RT_INI)).unwrap();
+        assert_eq!(ini.get("RtLinuxMemReserve").unwrap().get("Base").unwrap(), &IniTypes::Integer(24));
+        assert_eq!(ini.get("systemsettings").unwrap().get("host_name").unwrap(), &IniTypes::String("6463-Remote-Rio".to_string()));
+        assert_eq!(ini.get("eth0").unwrap().get("dhcpenabled").unwrap(), &IniTypes::String("1".to_string()));
+        assert_eq!(ini.to_string(), std::fs::read_to_string(fixture_root().join(NI_RT_INI)).unwrap());
+    }
+
+    #[test]
+    fn test_rio_data() {
+        let rio_data = RioData::new(&fixture_root()).unwrap();
+        let map = rio_data.to_json_map();
+        assert_eq!(map["serial"], "306ADDC");
+        assert_eq!(map["image_version"], "FRC_roboRIO_2024_v2.1");
+        assert_eq!(map["host_name"], "6463-Remote-Rio");
+        assert_eq!(map["no_app"], false);
+        assert_eq!(map["comment"], "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ");
+    }
+
+    #[test]
+    fn test_robot_command() {
+        let command = split_command("/usr/local/frc/JRE/bin/java -XX:+UseSerialGC -jar \"/home/lvuser/robotProject.jar\" \n");
+        assert_eq!(command.len(), 4);
+        assert_eq!(command[3], "/home/lvuser/robotProject.jar");
+
+        let cmdline = ["/usr/local/frc/JRE/bin/java", "-XX:+UseSerialGC", "-jar", "/home/lvuser/robotProject.jar"]
+            .map(String::from);
+        assert!(matches_robot_command(&cmdline, &command));
+        assert!(!matches_robot_command(&cmdline[..1], &command));
+    }
+
+    #[test]
+    fn test_write_eth0_keeps_other_lines() {
+        let dir = crate::test_util::TempDir::new("rio-eth0");
+        let path = dir.join("ni-rt.ini");
+        let fixture = std::fs::read_to_string(fixture_root().join(NI_RT_INI)).unwrap();
+        std::fs::write(&path, fixture.replace("[eth0]\n", "[eth0]\n;set by the imaging tool\n")).unwrap();
+
+        let mut ini = read_ini(path.clone()).unwrap();
+        let config = InterfaceConfig::Static {
+            address: Ipv4Addr::new(10, 64, 64, 2),
+            prefix: 24,
+            gateway: Some(Ipv4Addr::new(10, 64, 64, 1)),
+            dns: Vec::new(),
+        };
+        set_eth0(&mut ini, &config).unwrap();
+        ini.save().unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("[eth0]\n;set by the imaging tool\ndhcpenabled=\"0\"\n"));
+        assert!(saved.contains("llipaddr=\"169.254.84.93\"\n"));
+        assert!(saved.contains("IP_Address=\"10.64.64.2\"\n"));
+        assert!(saved.contains("Gateway=\"10.64.64.1\"\n"));
+
+        let mut ini = read_ini(path.clone()).unwrap();
+        set_eth0(&mut ini, &InterfaceConfig::Dhcp).unwrap();
+        ini.save().unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains(";set by the imaging tool\n"));
+        assert!(saved.contains("llipaddr=\"169.254.84.93\"\n"));
+        assert!(saved.contains("dhcpipaddr=\"10.64.60.53\"\n"));
+        assert!(!saved.contains("IP_Address") && !saved.contains("Gateway"));
+    }
+
+    #[test]
+    fn test_action_flags() {
+        assert_eq!(action_flags("start", false).unwrap().0, "-r");
+        assert!(matches!(action_flags("start", true), Err(ShiitakeError::Conflict(_))));
+        assert_eq!(action_flags("restart", true).unwrap().0, "-t -r");
+        assert_eq!(action_flags("restart", false).unwrap().0, "-t -r");
+        assert_eq!(action_flags("stop", true).unwrap().0, "-t");
+        assert!(matches!(action_flags("pause", true), Err(ShiitakeError::NotFound(_))));
     }
 
     #[test]
     fn test_get_serial() {
         assert_eq!(get_serial(PathBuf::from("serial.test")).unwrap(), "306ADDC".to_string());
+        assert_eq!(get_serial(fixture_root().join(ATOMICZYNQ_CONFIG)).unwrap(), "306ADDC".to_string());
     }
 }
\ No newline at end of file
@@ -0,0 +1,335 @@
+//! The sections and keys of ni-rt.ini the server understands: how NI encodes each value, its default and the JSON name it's exposed as.
+//! Reading fills in defaults for missing keys, writing validates every value before the ini is touched.
+
+use std::net::Ipv4Addr;
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::ini::{Ini, IniSection, IniTypes};
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "logging")]
+        tracing::warn!($($arg)*);
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("Unknown setting {0}")]
+    UnknownField(String),
+    #[error("{0} can't be changed")]
+    ReadOnly(String),
+    #[error("Invalid {field}: {reason}")]
+    Invalid { field: String, reason: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoolStyle {
+    ///`"True"`/`"False"`, used by systemsettings
+    TrueFalse,
+    ///`"1"`/`"0"`, used by the network interfaces
+    OneZero,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    Bool(BoolStyle),
+    String,
+    Hostname,
+    Ipv4,
+    ///one of a fixed set of strings
+    Choice(&'static [&'static str]),
+    ///NI's nibble swapped hex, see `decode_comment`
+    Comment,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub key: &'static str,
+    pub json: &'static str,
+    pub kind: FieldKind,
+    ///the value as it's written in the file, used when the key is missing
+    pub default: &'static str,
+    pub writable: bool,
+}
+
+impl Field {
+    const fn new(key: &'static str, json: &'static str, kind: FieldKind, default: &'static str) -> Self {
+        Field { key, json, kind, default, writable: true }
+    }
+
+    const fn read_only(key: &'static str, json: &'static str, kind: FieldKind, default: &'static str) -> Self {
+        Field { key, json, kind, default, writable: false }
+    }
+
+    fn decode(&self, raw: &str) -> Result<Value, String> {
+        match self.kind {
+            //NI isn't consistent about case so either style is accepted when reading
+            FieldKind::Bool(_) => match raw.to_lowercase().as_str() {
+                "true" | "1" => Ok(Value::Bool(true)),
+                "false" | "0" => Ok(Value::Bool(false)),
+                _ => Err(format!("{} isn't a boolean", raw)),
+            },
+            FieldKind::Comment => decode_comment(raw).map(Value::String),
+            FieldKind::String | FieldKind::Hostname | FieldKind::Ipv4 | FieldKind::Choice(_) => {
+                Ok(Value::String(raw.to_string()))
+            }
+        }
+    }
+
+    ///the key's value from `section`, or the default if it's missing or can't be decoded
+    pub fn read(&self, section: Option<&IniSection>) -> Value {
+        let default = || self.decode(self.default).unwrap_or(Value::Null);
+        match section.and_then(|section| section.get(self.key)) {
+            Some(value) => self.decode(&format!("{}", value)).unwrap_or_else(|_e| {
+                warn!("Using the default for {}: {}", self.key, _e);
+                default()
+            }),
+            None => default(),
+        }
+    }
+
+    ///validates a JSON value and turns it into what NI writes to the file
+    pub fn encode(&self, value: &Value) -> Result<String, SchemaError> {
+        let invalid = |reason: String| SchemaError::Invalid {
+            field: self.json.to_string(),
+            reason,
+        };
+        if let FieldKind::Bool(style) = self.kind {
+            let value = value.as_bool().ok_or_else(|| invalid("must be a boolean".to_string()))?;
+            let encoded = match (style, value) {
+                (BoolStyle::TrueFalse, true) => "True",
+                (BoolStyle::TrueFalse, false) => "False",
+                (BoolStyle::OneZero, true) => "1",
+                (BoolStyle::OneZero, false) => "0",
+            };
+            return Ok(encoded.to_string());
+        }
+
+        let value = value.as_str().ok_or_else(|| invalid("must be a string".to_string()))?;
+        if value.contains(['"', '\r', '\n']) {
+            return Err(invalid("can't contain quotes or newlines".to_string()));
+        }
+        match self.kind {
+            FieldKind::Hostname if !is_valid_hostname(value) => Err(invalid(
+                "must be 1-63 letters, digits and dashes, not starting or ending with a dash".to_string(),
+            )),
+            FieldKind::Ipv4 if value.parse::<Ipv4Addr>().is_err() => {
+                Err(invalid(format!("{} isn't an IPv4 address", value)))
+            }
+            FieldKind::Choice(choices) if !choices.contains(&value) => {
+                Err(invalid(format!("must be one of {}", choices.join(", "))))
+            }
+            FieldKind::Comment if !value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) => {
+                Err(invalid("can only contain printable ASCII".to_string()))
+            }
+            FieldKind::Comment => Ok(encode_comment(value)),
+            _ => Ok(value.to_string()),
+        }
+    }
+}
+
+fn is_valid_hostname(name: &str) -> bool {
+    (1..=63).contains(&name.len())
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+///The known keys of one ini section.
+#[derive(Debug)]
+pub struct Schema {
+    pub section: &'static str,
+    pub fields: &'static [Field],
+}
+
+impl Schema {
+    pub fn field(&self, json: &str) -> Option<&'static Field> {
+        self.fields.iter().find(|field| field.json == json)
+    }
+
+    ///every field by its JSON name, missing or malformed keys are filled in with their defaults
+    pub fn read(&self, ini: &Ini) -> Map<String, Value> {
+        let section = ini.get(self.section);
+        self.fields
+            .iter()
+            .map(|field| (field.json.to_string(), field.read(section)))
+            .collect()
+    }
+
+    pub fn read_field(&self, ini: &Ini, json: &str) -> Option<Value> {
+        Some(self.field(json)?.read(ini.get(self.section)))
+    }
+
+    fn encode(&self, values: &Map<String, Value>) -> Result<Vec<(&'static str, String)>, SchemaError> {
+        values
+            .iter()
+            .map(|(json, value)| {
+                let field = self.field(json).ok_or_else(|| SchemaError::UnknownField(json.clone()))?;
+                if !field.writable {
+                    return Err(SchemaError::ReadOnly(json.clone()));
+                }
+                Ok((field.key, field.encode(value)?))
+            })
+            .collect()
+    }
+
+    ///sets the given fields, leaving every other key alone.
+    ///Nothing is changed unless every value is valid.
+    pub fn write(&self, ini: &mut Ini, values: &Map<String, Value>) -> Result<(), SchemaError> {
+        let encoded = self.encode(values)?;
+        match ini.get_mut(self.section) {
+            Some(section) => {
+                for (key, value) in encoded {
+                    section.create_and_set(key, IniTypes::String(value));
+                }
+            }
+            None => ini.create_and_set(self.section_from(encoded)),
+        }
+        Ok(())
+    }
+
+    fn section_from(&self, encoded: Vec<(&'static str, String)>) -> IniSection {
+        let mut section = IniSection::new(self.section);
+        for (key, value) in encoded {
+            section.create_and_set(key, IniTypes::String(value));
+        }
+        section
+    }
+}
+
+pub const SYSTEM_SETTINGS: Schema = Schema {
+    section: "systemsettings",
+    fields: &[
+        Field::read_only("PrimaryMAC", "primary_mac", FieldKind::String, ""),
+        Field::new("NoFPGAApp.enabled", "no_fpga_app", FieldKind::Bool(BoolStyle::TrueFalse), "False"),
+        Field::new("ConsoleOut.enabled", "console_out", FieldKind::Bool(BoolStyle::TrueFalse), "False"),
+        Field::new("NoApp.enabled", "no_app", FieldKind::Bool(BoolStyle::TrueFalse), "False"),
+        Field::new("SafeMode.enabled", "safe_mode", FieldKind::Bool(BoolStyle::TrueFalse), "False"),
+        //turning ssh off from the web UI would lock teams out of their own Rio
+        Field::read_only("sshd.enabled", "sshd", FieldKind::Bool(BoolStyle::TrueFalse), "True"),
+        Field::new("host_name", "host_name", FieldKind::Hostname, "roboRIO"),
+        Field::new("Comment", "comment", FieldKind::Comment, ""),
+    ],
+};
+
+pub const ETH0: Schema = Schema {
+    section: "eth0",
+    fields: &[
+        Field::new("dhcpenabled", "dhcp_enabled", FieldKind::Bool(BoolStyle::OneZero), "1"),
+        Field::new("linklocalenabled", "link_local_enabled", FieldKind::Bool(BoolStyle::OneZero), "1"),
+        Field::new("IP_Address", "ip_address", FieldKind::Ipv4, ""),
+        Field::new("Subnet_Mask", "subnet_mask", FieldKind::Ipv4, "255.255.255.0"),
+        Field::new("Gateway", "gateway", FieldKind::Ipv4, ""),
+        Field::new("DNS_Address", "dns_address", FieldKind::Ipv4, ""),
+        Field::new("Mode", "mode", FieldKind::Choice(&["TCPIP", "Disabled"]), "TCPIP"),
+        Field::new("MediaMode", "media_mode", FieldKind::String, "Auto"),
+        Field::new("dhcpipaddr", "dhcp_ip_address", FieldKind::Ipv4, ""),
+        Field::read_only("llipaddr", "link_local_ip_address", FieldKind::Ipv4, ""),
+    ],
+};
+
+fn fix_comment_hex(hex_str: &str) -> String {
+    hex_str
+        .chars()
+        .map(|c| if (':'..='?').contains(&c) { (c as u8 + 39) as char } else { c })
+        .collect()
+}
+
+fn unfix_comment_hex(hex_str: &str) -> String {
+    hex_str
+        .chars()
+        .map(|c| if ('a'..='f').contains(&c) { (c as u8 - 39) as char } else { c })
+        .collect()
+}
+
+fn swap_hex(hex_str: &str) -> String {
+    format!("{}{}", &hex_str[1..], &hex_str[..1])
+}
+
+///NI writes each byte of the comment as two hex digits, low nibble first, with `:`-`?` standing in for `a`-`f`
+fn decode_comment(encoded: &str) -> Result<String, String> {
+    let chars = encoded.chars().collect::<Vec<char>>();
+    chars
+        .chunks(2)
+        .map(|pair| {
+            if pair.len() != 2 {
+                return Err("Comment has an odd number of hex digits".to_string());
+            }
+            u8::from_str_radix(&swap_hex(&fix_comment_hex(&pair.iter().collect::<String>())), 16)
+                .map(char::from)
+                .map_err(|_| "Comment isn't valid hex".to_string())
+        })
+        .collect()
+}
+
+fn encode_comment(decoded: &str) -> String {
+    decoded
+        .bytes()
+        .map(|byte| unfix_comment_hex(&swap_hex(&format!("{:02x}", byte))))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ini::parse_ini;
+    use serde_json::json;
+
+    const RT_INI: &str = "[systemsettings]\nNoFPGAApp.enabled=\"False\"\nConsoleOut.enabled=\"TRUE\"\nhost_name=\"6464-Rio\"\nSafeMode.enabled=\"maybe\"\n";
+
+    #[test]
+    fn test_read_defaults() {
+        let ini = parse_ini("ni-rt.ini".into(), RT_INI);
+        let map = SYSTEM_SETTINGS.read(&ini);
+        assert_eq!(map["console_out"], true);
+        assert_eq!(map["host_name"], "6464-Rio");
+        //missing and malformed keys fall back to their defaults
+        assert_eq!(map["no_app"], false);
+        assert_eq!(map["safe_mode"], false);
+        assert_eq!(map["sshd"], true);
+        assert_eq!(map["comment"], "");
+
+        let eth0 = ETH0.read(&ini);
+        assert_eq!(eth0["dhcp_enabled"], true);
+        assert_eq!(eth0["mode"], "TCPIP");
+    }
+
+    #[test]
+    fn test_write() {
+        let mut ini = parse_ini("ni-rt.ini".into(), RT_INI);
+        let values = json!({"no_fpga_app": true, "no_app": true, "comment": "hi"});
+        SYSTEM_SETTINGS.write(&mut ini, values.as_object().unwrap()).unwrap();
+        let section = ini.get("systemsettings").unwrap();
+        assert_eq!(section.get("NoFPGAApp.enabled"), Some(&IniTypes::String("True".to_string())));
+        assert_eq!(section.get("NoApp.enabled"), Some(&IniTypes::String("True".to_string())));
+        assert_eq!(SYSTEM_SETTINGS.read_field(&ini, "comment"), Some(json!("hi")));
+
+        let values = json!({"dhcp_enabled": false, "ip_address": "10.64.64.2"});
+        ETH0.write(&mut ini, values.as_object().unwrap()).unwrap();
+        assert_eq!(ini.get("eth0").unwrap().get("dhcpenabled"), Some(&IniTypes::String("0".to_string())));
+
+        //one bad value rejects the whole write
+        for values in [
+            json!({"no_app": false, "host_name": "-bad-"}),
+            json!({"no_app": false, "sshd": false}),
+            json!({"no_app": false, "bogus": 1}),
+            json!({"no_app": "yes"}),
+        ] {
+            assert!(SYSTEM_SETTINGS.write(&mut ini, values.as_object().unwrap()).is_err());
+        }
+        assert!(ETH0.write(&mut ini, json!({"mode": "Wifi"}).as_object().unwrap()).is_err());
+        assert_eq!(SYSTEM_SETTINGS.read_field(&ini, "no_app"), Some(json!(true)));
+    }
+
+    #[test]
+    fn test_comment() {
+        let encoded = "162636465666768696:6;6<6=6>6?607172737475767778797:7142434445464748494:4;4<4=4>4?405152535455565758595:5";
+        let decoded = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        assert_eq!(decode_comment(encoded).unwrap(), decoded);
+        assert_eq!(encode_comment(decoded), encoded);
+        assert!(decode_comment("123").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    extract::Query,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, Stream};
+use static_init::dynamic;
+use tokio::sync::watch;
+
+use crate::types::{HistoryEntry, LiveSnapshot, Processes};
+
+///The latest samples taken by the background sampler.
+///Every stream subscriber reads from here so the expensive refresh only happens once per sample.
+pub struct LiveFeed {
+    stats: watch::Sender<Option<Arc<HistoryEntry>>>,
+    processes: watch::Sender<Option<Arc<Processes>>>,
+}
+
+impl LiveFeed {
+    fn new() -> Self {
+        LiveFeed {
+            stats: watch::channel(None).0,
+            processes: watch::channel(None).0,
+        }
+    }
+
+    pub fn publish_stats(&self, entry: HistoryEntry) {
+        self.stats.send_replace(Some(Arc::new(entry)));
+    }
+
+    pub fn latest_stats(&self) -> Option<Arc<HistoryEntry>> {
+        self.stats.borrow().clone()
+    }
+
+    ///processes are only measured while at least one subscriber asked for them
+    pub fn wants_processes(&self) -> bool {
+        self.processes.receiver_count() > 0
+    }
+
+    pub fn publish_processes(&self, processes: Processes) {
+        self.processes.send_replace(Some(Arc::new(processes)));
+    }
+}
+
+#[dynamic]
+pub static LIVE_FEED: LiveFeed = LiveFeed::new();
+
+#[derive(serde::Deserialize)]
+pub struct StreamQuery {
+    ///milliseconds between frames, clamped to the sampler interval
+    interval: Option<u64>,
+    fields: Option<String>,
+    #[serde(default)]
+    processes: bool,
+}
+
+struct StreamState {
+    ticker: tokio::time::Interval,
+    stats: watch::Receiver<Option<Arc<HistoryEntry>>>,
+    processes: Option<watch::Receiver<Option<Arc<Processes>>>>,
+    fields: Option<Vec<String>>,
+    last_timestamp: u64,
+}
+
+impl StreamState {
+    ///the newest sample as a frame, or None if it has already been sent
+    fn next_frame(&mut self) -> Option<LiveSnapshot> {
+        let entry = self.stats.borrow().clone()?;
+        if entry.timestamp == self.last_timestamp {
+            return None;
+        }
+        self.last_timestamp = entry.timestamp;
+
+        let mut stats = entry.stats.clone();
+        if let Some(fields) = &self.fields {
+            stats.retain_fields(&fields.iter().map(String::as_str).collect::<Vec<_>>());
+        }
+        let processes = self
+            .processes
+            .as_ref()
+            .and_then(|processes| processes.borrow().as_deref().cloned());
+
+        Some(LiveSnapshot {
+            timestamp: entry.timestamp,
+            stats,
+            processes,
+        })
+    }
+}
+
+pub async fn stats_stream(
+    Query(query): Query<StreamQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let sample_interval = crate::config::get().sampling.interval();
+    let interval = query
+        .interval
+        .map(Duration::from_millis)
+        .unwrap_or(sample_interval)
+        .max(sample_interval);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let state = StreamState {
+        ticker,
+        stats: LIVE_FEED.stats.subscribe(),
+        processes: query.processes.then(|| LIVE_FEED.processes.subscribe()),
+        fields: query
+            .fields
+            .map(|fields| fields.split(',').map(str::to_string).collect()),
+        last_timestamp: 0,
+    };
+
+    let frames = stream::unfold(state, |mut state| async move {
+        loop {
+            state.ticker.tick().await;
+            if let Some(frame) = state.next_frame() {
+                let event = Event::default()
+                    .event("stats")
+                    .json_data(&frame)
+                    .unwrap_or_else(|_| Event::default().comment("failed to serialize frame"));
+                return Some((Ok(event), state));
+            }
+        }
+    });
+
+    Sse::new(frames).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn subscribe(feed: &LiveFeed) -> StreamState {
+        StreamState {
+            ticker: tokio::time::interval(Duration::from_secs(1)),
+            stats: feed.stats.subscribe(),
+            processes: None,
+            fields: None,
+            last_timestamp: 0,
+        }
+    }
+
+    fn entry(timestamp: u64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            stats: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_every_subscriber_gets_a_sample() {
+        let feed = LiveFeed::new();
+        let (mut first, mut second) = (subscribe(&feed), subscribe(&feed));
+        assert!(first.next_frame().is_none());
+        feed.publish_stats(entry(1000));
+        assert_eq!(first.next_frame().map(|frame| frame.timestamp), Some(1000));
+        assert_eq!(second.next_frame().map(|frame| frame.timestamp), Some(1000));
+        //a sample is only sent once
+        assert!(first.next_frame().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_skips_samples() {
+        let feed = LiveFeed::new();
+        let (mut lagging, mut current) = (subscribe(&feed), subscribe(&feed));
+        //publishing never waits on a subscriber that hasn't read the last sample
+        for timestamp in 1..=5 {
+            feed.publish_stats(entry(timestamp * 1000));
+            assert_eq!(current.next_frame().map(|frame| frame.timestamp), Some(timestamp * 1000));
+        }
+        assert_eq!(lagging.next_frame().map(|frame| frame.timestamp), Some(5000));
+        assert!(lagging.next_frame().is_none());
+    }
+}
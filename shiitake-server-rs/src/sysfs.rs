@@ -0,0 +1,258 @@
+//! Readers for the `/sys` trees sysinfo doesn't cover.
+//! Each reader takes the root of its tree so it can be pointed at a fixture directory in tests.
+
+use std::path::{Path, PathBuf};
+
+use crate::types::{CpuThrottleEntry, NetLinkEntry, TemperatureEntry, TripPoint};
+
+pub const THERMAL_ROOT: &str = "/sys/class/thermal";
+pub const HWMON_ROOT: &str = "/sys/class/hwmon";
+pub const CPU_ROOT: &str = "/sys/devices/system/cpu";
+pub const NET_ROOT: &str = "/sys/class/net";
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    read_trimmed(path)?.parse().ok()
+}
+
+///sysfs reports temperatures in millidegrees celsius
+fn read_celsius(path: &Path) -> Option<f64> {
+    Some(read_trimmed(path)?.parse::<i64>().ok()? as f64 / 1000.0)
+}
+
+///the entries of `root` whose name starts with `prefix`, sorted by the number following the prefix
+fn numbered_entries(root: &Path, prefix: &str) -> Vec<(String, PathBuf)> {
+    let Ok(dir) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut entries = dir
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let index = name.strip_prefix(prefix)?.parse::<u32>().ok()?;
+            Some((index, name, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(index, _, _)| *index);
+    entries.into_iter().map(|(_, name, path)| (name, path)).collect()
+}
+
+pub fn read_thermal_zones(root: &Path) -> Vec<TemperatureEntry> {
+    let mut temperatures = Vec::new();
+    for (name, path) in numbered_entries(root, "thermal_zone") {
+        let Some(current) = read_celsius(&path.join("temp")) else {
+            continue;
+        };
+        let mut trip_points = Vec::new();
+        for i in 0.. {
+            let Some(temperature) = read_celsius(&path.join(format!("trip_point_{}_temp", i))) else {
+                break;
+            };
+            trip_points.push(TripPoint {
+                kind: read_trimmed(&path.join(format!("trip_point_{}_type", i)))
+                    .unwrap_or_else(|| String::from("unknown")),
+                temperature,
+            });
+        }
+        let critical = trip_points
+            .iter()
+            .find(|trip| trip.kind == "critical")
+            .map(|trip| trip.temperature);
+        temperatures.push(TemperatureEntry {
+            label: read_trimmed(&path.join("type")).unwrap_or_else(|| name.clone()),
+            source: name,
+            current,
+            critical,
+            trip_points,
+        });
+    }
+    temperatures
+}
+
+pub fn read_hwmon(root: &Path) -> Vec<TemperatureEntry> {
+    let mut temperatures = Vec::new();
+    for (name, path) in numbered_entries(root, "hwmon") {
+        let chip = read_trimmed(&path.join("name")).unwrap_or_else(|| name.clone());
+        //hwmon sensors are numbered from 1
+        for i in 1..=32 {
+            let Some(current) = read_celsius(&path.join(format!("temp{}_input", i))) else {
+                continue;
+            };
+            let mut trip_points = Vec::new();
+            for kind in ["max", "crit"] {
+                if let Some(temperature) = read_celsius(&path.join(format!("temp{}_{}", i, kind))) {
+                    trip_points.push(TripPoint {
+                        kind: kind.to_string(),
+                        temperature,
+                    });
+                }
+            }
+            temperatures.push(TemperatureEntry {
+                source: format!("{}/temp{}", name, i),
+                label: read_trimmed(&path.join(format!("temp{}_label", i)))
+                    .map(|label| format!("{} {}", chip, label))
+                    .unwrap_or_else(|| format!("{} temp{}", chip, i)),
+                current,
+                critical: read_celsius(&path.join(format!("temp{}_crit", i))),
+                trip_points,
+            });
+        }
+    }
+    temperatures
+}
+
+pub fn read_cpu_throttle(root: &Path) -> Vec<CpuThrottleEntry> {
+    let mut cpus = Vec::new();
+    for (name, path) in numbered_entries(root, "cpu") {
+        let cpufreq = path.join("cpufreq");
+        //frequencies are in kHz, report them in Hz like `cpu_speed`
+        let Some(current_frequency) = read_u64(&cpufreq.join("scaling_cur_freq")) else {
+            continue;
+        };
+        let max_frequency = read_u64(&cpufreq.join("scaling_max_freq")).unwrap_or(current_frequency);
+        let hardware_max_frequency = read_u64(&cpufreq.join("cpuinfo_max_freq")).unwrap_or(max_frequency);
+        cpus.push(CpuThrottleEntry {
+            cpu: name["cpu".len()..].parse().unwrap_or(0),
+            current_frequency: current_frequency * 1000,
+            max_frequency: max_frequency * 1000,
+            hardware_max_frequency: hardware_max_frequency * 1000,
+            governor: read_trimmed(&cpufreq.join("scaling_governor")),
+            throttled: max_frequency < hardware_max_frequency,
+            throttle_count: read_u64(&path.join("thermal_throttle/core_throttle_count")),
+        });
+    }
+    cpus
+}
+
+pub fn read_net_links(root: &Path) -> Vec<NetLinkEntry> {
+    let Ok(dir) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut links = dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let path = entry.path();
+            NetLinkEntry {
+                interface: entry.file_name().to_string_lossy().to_string(),
+                //loopback and tunnels report an all zero or empty address
+                mac: read_trimmed(&path.join("address"))
+                    .filter(|mac| !mac.is_empty() && mac.chars().any(|c| c != '0' && c != ':')),
+                mtu: read_u64(&path.join("mtu")),
+                operstate: read_trimmed(&path.join("operstate")).unwrap_or_else(|| String::from("unknown")),
+                carrier: read_u64(&path.join("carrier")).map(|carrier| carrier == 1),
+                //-1 without a link, which doesn't parse as a u64
+                speed: read_u64(&path.join("speed")),
+                duplex: read_trimmed(&path.join("duplex")).filter(|duplex| duplex != "unknown"),
+            }
+        })
+        .collect::<Vec<_>>();
+    links.sort_by(|a, b| a.interface.cmp(&b.interface));
+    links
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn fixture(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let root = TempDir::new(&format!("sysfs-{}", name));
+        for (file, contents) in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_thermal_zones() {
+        let root = fixture(
+            "thermal",
+            &[
+                ("thermal_zone0/type", "cpu-thermal\n"),
+                ("thermal_zone0/temp", "54321\n"),
+                ("thermal_zone0/trip_point_0_type", "passive\n"),
+                ("thermal_zone0/trip_point_0_temp", "85000\n"),
+                ("thermal_zone0/trip_point_1_type", "critical\n"),
+                ("thermal_zone0/trip_point_1_temp", "105000\n"),
+                ("cooling_device0/type", "fan\n"),
+            ],
+        );
+        let zones = read_thermal_zones(&root);
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].label, "cpu-thermal");
+        assert_eq!(zones[0].current, 54.321);
+        assert_eq!(zones[0].critical, Some(105.0));
+        assert_eq!(zones[0].trip_points.len(), 2);
+    }
+
+    #[test]
+    fn test_hwmon() {
+        let root = fixture(
+            "hwmon",
+            &[
+                ("hwmon0/name", "coretemp\n"),
+                ("hwmon0/temp1_input", "48000\n"),
+                ("hwmon0/temp1_label", "Package id 0\n"),
+                ("hwmon0/temp1_crit", "100000\n"),
+            ],
+        );
+        let sensors = read_hwmon(&root);
+        assert_eq!(sensors.len(), 1);
+        assert_eq!(sensors[0].label, "coretemp Package id 0");
+        assert_eq!(sensors[0].critical, Some(100.0));
+    }
+
+    #[test]
+    fn test_cpu_throttle() {
+        let root = fixture(
+            "cpufreq",
+            &[
+                ("cpu0/cpufreq/scaling_cur_freq", "600000\n"),
+                ("cpu0/cpufreq/scaling_max_freq", "1000000\n"),
+                ("cpu0/cpufreq/cpuinfo_max_freq", "1800000\n"),
+                ("cpu0/cpufreq/scaling_governor", "ondemand\n"),
+                ("cpufreq/policy0/scaling_cur_freq", "600000\n"),
+            ],
+        );
+        let cpus = read_cpu_throttle(&root);
+        assert_eq!(cpus.len(), 1);
+        assert_eq!(cpus[0].current_frequency, 600_000_000);
+        assert!(cpus[0].throttled);
+        assert_eq!(cpus[0].governor.as_deref(), Some("ondemand"));
+    }
+
+    #[test]
+    fn test_net_links() {
+        let root = fixture(
+            "net",
+            &[
+                ("eth0/address", "00:80:2f:25:7e:70\n"),
+                ("eth0/mtu", "1500\n"),
+                ("eth0/operstate", "up\n"),
+                ("eth0/carrier", "1\n"),
+                ("eth0/speed", "1000\n"),
+                ("eth0/duplex", "full\n"),
+                ("lo/address", "00:00:00:00:00:00\n"),
+                ("lo/mtu", "65536\n"),
+                ("lo/operstate", "unknown\n"),
+                ("lo/speed", "-1\n"),
+            ],
+        );
+        let links = read_net_links(&root);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].mac.as_deref(), Some("00:80:2f:25:7e:70"));
+        assert_eq!(links[0].speed, Some(1000));
+        assert_eq!(links[0].carrier, Some(true));
+        assert_eq!(links[1].interface, "lo");
+        assert_eq!(links[1].mac, None);
+        assert_eq!(links[1].speed, None);
+        assert_eq!(links[1].carrier, None);
+    }
+}
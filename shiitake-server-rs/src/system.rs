@@ -1,9 +1,9 @@
 use crate::ShiitakeError;
 use std::path::PathBuf;
 
-#[cfg(target_vendor = "roborio")]
+#[cfg(feature = "rio")]
 fn get_uuid() -> Result<u128, ShiitakeError> {
-    let path = PathBuf::from("/var/lib/compactrio/atomiczynq.config");
+    let path = crate::config::get().rio.path(crate::rio_interface::ATOMICZYNQ_CONFIG);
     let file = std::fs::read_to_string(path)?;
     const PREFIX: &str = "Serial=";
     const SERIAL_SIZE: usize = 16;
@@ -35,7 +35,7 @@ fn get_uuid() -> Result<u128, ShiitakeError> {
     Ok(serial_int)
 }
 
-#[cfg(not(target_vendor = "roborio"))]
+#[cfg(not(feature = "rio"))]
 fn get_uuid() -> Result<u128, ShiitakeError> {
     let path = PathBuf::from("/etc/machine-id");
     let file = std::fs::read_to_string(path)?;
@@ -46,9 +46,9 @@ fn get_uuid() -> Result<u128, ShiitakeError> {
 }
 
 
-#[cfg(target_vendor = "roborio")]
+#[cfg(feature = "rio")]
 fn get_hostname() -> Result<String, ShiitakeError> {
-    let path = PathBuf::from("/etc/natinst/share/ni-rt.ini");
+    let path = crate::config::get().rio.path(crate::rio_interface::NI_RT_INI);
     let file = std::fs::read_to_string(path)?;
 
     for line in file.lines() {
@@ -60,7 +60,7 @@ fn get_hostname() -> Result<String, ShiitakeError> {
     Err(ShiitakeError::DataNotFound)
 }
 
-#[cfg(not(target_vendor = "roborio"))]
+#[cfg(not(feature = "rio"))]
 fn get_hostname() -> Result<String, ShiitakeError> {
     let path = PathBuf::from("/etc/hostname");
     let file = std::fs::read_to_string(path)?;
@@ -69,9 +69,9 @@ fn get_hostname() -> Result<String, ShiitakeError> {
     Ok(stripped)
 }
 
-#[cfg(target_vendor = "roborio")]
+#[cfg(feature = "rio")]
 fn get_os() -> Result<String, ShiitakeError> {
-    let path = PathBuf::from("/etc/natinst/share/scs_imagemetadata.ini");
+    let path = crate::config::get().rio.path(crate::rio_interface::IMAGE_METADATA_INI);
     let file = std::fs::read_to_string(path)?;
 
     for line in file.lines() {
@@ -84,7 +84,7 @@ fn get_os() -> Result<String, ShiitakeError> {
     Err(ShiitakeError::DataNotFound)
 }
 
-#[cfg(not(target_vendor = "roborio"))]
+#[cfg(not(feature = "rio"))]
 fn get_os() -> Result<String, ShiitakeError> {
     let path = PathBuf::from("/etc/os-release");
     let file = std::fs::read_to_string(path)?;
@@ -142,8 +142,10 @@ fn total_memory() -> Result<u64, ShiitakeError> {
 }
 
 pub fn make_summary() -> crate::types::Summary {
+    let hostname = get_hostname().unwrap_or_else(|_| String::from("Unknown"));
     crate::types::Summary {
-        hostname: get_hostname().unwrap_or_else(|_| String::from("Unknown")),
+        team: crate::network::team::current_team(&hostname),
+        hostname,
         os: get_os().unwrap_or_else(|_| String::from("Unknown")),
         uuid: get_uuid().unwrap_or_else(|_| 0),
         cpu_cores: cpu_cores().unwrap_or_else(|_| 0),
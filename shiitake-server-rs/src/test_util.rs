@@ -0,0 +1,48 @@
+//! Helpers shared by the unit tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+///A scratch directory under the system temp dir.
+///It's removed when dropped, which also happens when the test panics, so failing runs don't leave it behind.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    ///`name` keeps tests running at the same time apart, so it has to be unique across the crate
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("shiitake-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_removed_on_panic() {
+        let path = std::panic::catch_unwind(|| {
+            let dir = TempDir::new("test-util-panic");
+            std::fs::write(dir.join("file"), "contents").unwrap();
+            assert!(dir.exists());
+            std::panic::resume_unwind(Box::new(dir.to_path_buf()));
+        })
+        .unwrap_err();
+        assert!(!path.downcast_ref::<PathBuf>().unwrap().exists());
+    }
+}
@@ -7,11 +7,25 @@ pub mod routes;
 
 pub const REBOOT_VERIFICATION: &str = "please";
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct NetworkUsageEntry {
     pub interface: String,
+    ///bytes received since the previous server side sample
     pub rx: u64,
+    ///bytes transmitted since the previous server side sample
     pub tx: u64,
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+    pub rx_packets_per_sec: f64,
+    pub tx_packets_per_sec: f64,
+    pub total_rx: u64,
+    pub total_tx: u64,
+    pub total_rx_packets: u64,
+    pub total_tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,6 +35,118 @@ pub struct DiskUsageEntry {
     pub used: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DiskIoEntry {
+    pub device: String,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    pub total_read: u64,
+    pub total_written: u64,
+}
+
+///pressure stall averages are percentages of wall time, `total` is in microseconds
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct PressureAverages {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Pressure {
+    pub some: PressureAverages,
+    ///not reported for cpu pressure on older kernels
+    pub full: Option<PressureAverages>,
+}
+
+///all sizes are in bytes
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MemoryStats {
+    pub total: u64,
+    pub available: u64,
+    pub free: u64,
+    pub buffers: u64,
+    pub cached: u64,
+    pub shmem: u64,
+    pub swap_total: u64,
+    pub swap_used: u64,
+    pub slab: u64,
+    ///only on kernels built with pressure stall information
+    pub pressure: Option<Pressure>,
+}
+
+impl MemoryStats {
+    ///memory that can't be reclaimed without swapping, matches the old `memory_usage` value
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct LoadStats {
+    pub load_1: f64,
+    pub load_5: f64,
+    pub load_15: f64,
+    ///tasks currently runnable, ie the run queue length
+    pub running: u64,
+    ///tasks blocked waiting on io
+    pub blocked: u64,
+    pub context_switches_per_sec: f64,
+    pub interrupts_per_sec: f64,
+    pub cpu_pressure: Option<Pressure>,
+    pub io_pressure: Option<Pressure>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TripPoint {
+    pub kind: String,
+    pub temperature: f64,
+}
+
+///temperatures are in degrees celsius
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TemperatureEntry {
+    ///the sysfs node the reading came from, eg `thermal_zone0` or `hwmon1/temp2`
+    pub source: String,
+    pub label: String,
+    pub current: f64,
+    pub critical: Option<f64>,
+    pub trip_points: Vec<TripPoint>,
+}
+
+///frequencies are in Hz
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CpuThrottleEntry {
+    pub cpu: u32,
+    pub current_frequency: u64,
+    pub max_frequency: u64,
+    pub hardware_max_frequency: u64,
+    pub governor: Option<String>,
+    ///the allowed max frequency has been capped below what the hardware supports
+    pub throttled: bool,
+    ///thermal throttling events since boot, only reported by some cpus
+    pub throttle_count: Option<u64>,
+}
+
+///the link level state of a network interface, speeds are in Mb/s
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NetLinkEntry {
+    pub interface: String,
+    pub mac: Option<String>,
+    pub mtu: Option<u64>,
+    ///`up`, `down`, `dormant`, `unknown`...
+    pub operstate: String,
+    ///whether a cable or association is detected, only readable while the interface is up
+    pub carrier: Option<bool>,
+    ///only reported by wired interfaces with a link
+    pub speed: Option<u64>,
+    pub duplex: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Stats {
@@ -28,12 +154,80 @@ pub struct Stats {
     pub cpu_speed: Option<Vec<u64>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cpu_usage: Option<Vec<f64>>,
+    ///kept for older clients, `memory` has the full breakdown
     #[serde(skip_serializing_if = "Option::is_none")]
     pub memory_usage: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load: Option<LoadStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub network_usage: Option<Vec<NetworkUsageEntry>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub disk_usage: Option<Vec<DiskUsageEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_io: Option<Vec<DiskIoEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperatures: Option<Vec<TemperatureEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_throttle: Option<Vec<CpuThrottleEntry>>,
+}
+
+impl Stats {
+    ///clears every field whose serialized name isn't in `fields`
+    pub fn retain_fields(&mut self, fields: &[&str]) {
+        if !fields.contains(&"cpuSpeed") {
+            self.cpu_speed = None;
+        }
+        if !fields.contains(&"cpuUsage") {
+            self.cpu_usage = None;
+        }
+        if !fields.contains(&"memoryUsage") {
+            self.memory_usage = None;
+        }
+        if !fields.contains(&"memory") {
+            self.memory = None;
+        }
+        if !fields.contains(&"load") {
+            self.load = None;
+        }
+        if !fields.contains(&"networkUsage") {
+            self.network_usage = None;
+        }
+        if !fields.contains(&"diskUsage") {
+            self.disk_usage = None;
+        }
+        if !fields.contains(&"diskIo") {
+            self.disk_io = None;
+        }
+        if !fields.contains(&"temperatures") {
+            self.temperatures = None;
+        }
+        if !fields.contains(&"cpuThrottle") {
+            self.cpu_throttle = None;
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    ///milliseconds since the unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+///one frame of the live stats stream
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveSnapshot {
+    ///milliseconds since the unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub stats: Stats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processes: Option<Processes>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -46,6 +240,8 @@ pub struct Summary {
     pub uuid: u128,
     pub cpu_cores: u8,
     pub total_memory: u64,
+    ///FRC team number, from a roboRIO hostname or a `10.TE.AM.x` address
+    pub team: Option<u16>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -57,20 +253,84 @@ pub struct Process {
     pub memory_usage: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessDetail {
+    pub pid: u32,
+    pub parent_pid: u32,
+    ///the kernel's name for the process, not truncated by a missing exe path
+    pub name: String,
+    pub exe: Option<String>,
+    pub cmdline: Vec<String>,
+    pub uid: u32,
+    pub user: Option<String>,
+    ///the single letter state from `/proc/<pid>/stat`, eg `R`, `S`, `D`
+    pub state: String,
+    ///milliseconds since the unix epoch
+    pub start_time: u64,
+    pub threads: u64,
+    ///None when we aren't allowed to list the process's fds
+    pub open_fds: Option<u64>,
+    pub sched_policy: String,
+    pub rt_priority: u32,
+    pub nice: i32,
+    pub cpu_affinity: Vec<u32>,
+    ///bytes
+    pub virtual_memory: u64,
+    ///bytes
+    pub resident_memory: u64,
+    ///bytes
+    pub shared_memory: u64,
+    ///seconds spent in user mode
+    pub user_cpu_time: f64,
+    ///seconds spent in kernel mode
+    pub system_cpu_time: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessTreeNode {
+    pub pid: u32,
+    pub name: String,
+    pub cpu_usage: f64,
+    pub memory_usage: u64,
+    ///this process plus every descendant
+    pub subtree_cpu_usage: f64,
+    ///this process plus every descendant
+    pub subtree_memory_usage: u64,
+    pub children: Vec<ProcessTreeNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadInfo {
+    pub tid: u32,
+    pub name: String,
+    pub state: String,
+    ///percent of total cpu, same scale as `Process::cpu_usage`
+    pub cpu_usage: f64,
+    pub sched_policy: String,
+    pub rt_priority: u32,
+    pub nice: i32,
+}
+
 pub fn timespec_to_hex(seconds: i64, nanoseconds: i64) -> String {
     let hex_seconds = format!("{:x}", seconds);
     let hex_nanoseconds = format!("{:x}", nanoseconds);
     format!("{}:{}", hex_seconds, hex_nanoseconds)
 }
 
-pub fn hex_to_timespec(hex: String) -> (i64, i64) {
-    let split = hex.split(":");
-    let mut split = split.into_iter();
-    let seconds = i64::from_str_radix(split.next().expect("hex split was not long enough"), 16)
-        .expect("Failed to parse seconds from hex string");
-    let nanoseconds = i64::from_str_radix(split.next().expect("hex split was not long enough"), 16)
-        .expect("Failed to parse nanoseconds from hex string");
-    (seconds, nanoseconds)
+pub fn hex_to_timespec(hex: String) -> Result<(i64, i64), crate::ShiitakeError> {
+    let (seconds, nanoseconds) = hex
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| crate::ShiitakeError::BadRequest(format!("Expected seconds:nanoseconds, got {:?}", hex)))?;
+    let seconds = i64::from_str_radix(seconds, 16)?;
+    let nanoseconds = i64::from_str_radix(nanoseconds, 16)?;
+    if !(0..1_000_000_000).contains(&nanoseconds) {
+        return Err(crate::ShiitakeError::BadRequest("Nanoseconds out of range".to_string()));
+    }
+    Ok((seconds, nanoseconds))
 }
 
 #[cfg(test)]
@@ -80,11 +340,19 @@ mod test {
         let timespec_mock = (123456, 789);
         let hex = super::timespec_to_hex(timespec_mock.0, timespec_mock.1);
         println!("{}", hex);
-        let timespeck_modck2 = super::hex_to_timespec(hex);
+        let timespeck_modck2 = super::hex_to_timespec(hex).unwrap();
         assert_eq!(timespec_mock.0, timespeck_modck2.0);
         assert_eq!(timespec_mock.1, timespeck_modck2.1);
     }
 
+    #[test]
+    fn test_hex_malformed() {
+        assert!(super::hex_to_timespec("not a time".to_string()).is_err());
+        assert!(super::hex_to_timespec("1e240".to_string()).is_err());
+        assert!(super::hex_to_timespec("1e240:zz".to_string()).is_err());
+        assert!(super::hex_to_timespec("1e240:ffffffff".to_string()).is_err());
+    }
+
     #[test]
     fn test_stat() {
         use super::Stats;
@@ -92,16 +360,29 @@ mod test {
             cpu_speed: Some(vec![1000, 2000, 3000]),
             cpu_usage: Some(vec![1.0, 2.0, 3.0]),
             memory_usage: Some(20000),
+            memory: Some(super::MemoryStats {
+                total: 50000,
+                available: 30000,
+                ..Default::default()
+            }),
+            load: Some(super::LoadStats {
+                load_1: 0.5,
+                load_5: 0.25,
+                load_15: 0.1,
+                ..Default::default()
+            }),
             network_usage: Some(vec![
                 super::NetworkUsageEntry {
                     interface: "eth0".to_string(),
                     rx: 1000,
                     tx: 2000,
+                    ..Default::default()
                 },
                 super::NetworkUsageEntry {
                     interface: "eth1".to_string(),
                     rx: 3000,
                     tx: 4000,
+                    ..Default::default()
                 },
             ]),
             disk_usage: Some(vec![
@@ -116,6 +397,30 @@ mod test {
                     used: 40000,
                 },
             ]),
+            disk_io: Some(vec![
+                super::DiskIoEntry {
+                    device: "mmcblk0".to_string(),
+                    read_bytes_per_sec: 4096.0,
+                    write_bytes_per_sec: 512.0,
+                    read_iops: 1.0,
+                    write_iops: 1.0,
+                    total_read: 100000,
+                    total_written: 20000,
+                },
+            ]),
+            temperatures: Some(vec![
+                super::TemperatureEntry {
+                    source: "thermal_zone0".to_string(),
+                    label: "cpu-thermal".to_string(),
+                    current: 54.3,
+                    critical: Some(105.0),
+                    trip_points: vec![super::TripPoint {
+                        kind: "critical".to_string(),
+                        temperature: 105.0,
+                    }],
+                },
+            ]),
+            cpu_throttle: None,
         };
         let stats_mock_json = serde_json::to_string(&stats_mock).unwrap();
         println!("{}", stats_mock_json);
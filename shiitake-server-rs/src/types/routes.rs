@@ -9,10 +9,39 @@ macro_rules! route {
 
 route!(ROOT, "/");
 route!(STATS, "/stats");
+route!(STATS_HISTORY, "/stats/history");
+route!(STATS_STREAM, "/stats/stream");
+route!(METRICS, "/metrics");
 route!(PROCESSES, "/processes");
+route!(PROCESS_TREE, "/processes/tree");
+route!(PROCESS_DETAIL, "/processes/:pid");
+route!(PROCESS_THREADS, "/processes/:pid/threads");
+route!(PROCESS_SIGNAL, "/processes/:pid/signal");
+route!(PROCESS_RENICE, "/processes/:pid/renice");
+route!(PROCESS_SCHED, "/processes/:pid/sched");
+route!(PROCESS_AFFINITY, "/processes/:pid/affinity");
 route!(TIME, "/time");
 route!(REBOOT, "/reboot");
 route!(RIO, "/rio");
+route!(RIO_USER_PROGRAM, "/rio/user_program");
+route!(RIO_USER_PROGRAM_ACTION, "/rio/user_program/:action");
+route!(RIO_PING, "/nisysdetails/ping");
 route!(SYSTEM_SUMMARY, "/system_summary");
 route!(UPTIME, "/uptime");
-route!(SET_IP, "/set_ip");
\ No newline at end of file
+route!(NETWORK_INTERFACES, "/network/interfaces");
+route!(NETWORK_NEIGHBORS, "/network/neighbors");
+route!(NETWORK_TEAM, "/network/team");
+route!(NETWORK_INTERFACE, "/network/:interface");
+route!(NETWORK_INTERFACE_CONFIRM, "/network/:interface/confirm");
+route!(NETWORK_INTERFACE_REVERT, "/network/:interface/revert");
+route!(SET_IP, "/set_ip");
+route!(AUTH_LOGIN, "/auth/login");
+route!(AUTH_LOGOUT, "/auth/logout");
+route!(AUTH_STATUS, "/auth/status");
+route!(CONFIG_BACKUPS, "/config/backups");
+route!(CONFIG_BACKUP_DIFF, "/config/backups/:id/diff");
+route!(CONFIG_BACKUP_RESTORE, "/config/backups/:id/restore");
+route!(DIAGNOSTICS_PING, "/diagnostics/ping");
+route!(DIAGNOSTICS_TRACEROUTE, "/diagnostics/traceroute");
+route!(DIAGNOSTICS_TCP, "/diagnostics/tcp");
+route!(DIAGNOSTICS_DNS, "/diagnostics/dns");
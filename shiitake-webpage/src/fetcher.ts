@@ -105,3 +105,35 @@ export async function getSummary(): Promise<shiitake.Summary> {
         return out;
     }
 }
+
+export async function getAuthStatus(): Promise<shiitake.AuthStatus> {
+    if (IS_DEBUG_BUILD) {
+        return new shiitake.AuthStatus(false, true, true);
+    } else {
+        const response = await shiitakeFetch(shiitake.AUTH_STATUS_ROUTE);
+        const json = await response.json();
+        return shiitake.AuthStatus.fromJson(json);
+    }
+}
+
+/**
+ * Logs in with the password, the server answers with a session cookie the browser sends on every later request
+ */
+export async function login(password: string): Promise<boolean> {
+    if (IS_DEBUG_BUILD) {
+        return true;
+    } else {
+        const response = await shiitakeFetch(shiitake.AUTH_LOGIN_ROUTE, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ password: password })
+        });
+        return response.ok;
+    }
+}
+
+export async function logout(): Promise<void> {
+    if (!IS_DEBUG_BUILD) {
+        await shiitakeFetch(shiitake.AUTH_LOGOUT_ROUTE, { method: "POST" });
+    }
+}
@@ -45,7 +45,8 @@ export function mockSummary(): Promise<shiitake.Summary> {
             "1.0",
             999,
             CORE_COUNT,
-            TOTAL_RAM
+            TOTAL_RAM,
+            6464
         ));
     });
 }
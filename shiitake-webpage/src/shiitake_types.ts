@@ -3,6 +3,9 @@ export const PROCESSES_ROUTE = "/processes";
 export const TIME_ROUTE = "/time";
 export const UPTIME_ROUTE = "/uptime";
 export const SUMMARY_ROUTE = "/system_summary";
+export const AUTH_LOGIN_ROUTE = "/auth/login";
+export const AUTH_LOGOUT_ROUTE = "/auth/logout";
+export const AUTH_STATUS_ROUTE = "/auth/status";
 
 export class NetworkUsageEntry {
     interface_: string;
@@ -187,6 +190,7 @@ export class Summary {
     uuid: number;
     cpuCores: number;
     totalMemory: number;
+    team: number | null;
 
     constructor(
         hostname: string,
@@ -195,7 +199,8 @@ export class Summary {
         webpageVersion: string,
         uuid: number,
         cpuCores: number,
-        totalMemory: number
+        totalMemory: number,
+        team: number | null
     ) {
         this.hostname = hostname;
         this.os = os;
@@ -204,6 +209,7 @@ export class Summary {
         this.uuid = uuid;
         this.cpuCores = cpuCores;
         this.totalMemory = totalMemory;
+        this.team = team;
     }
 
     static fromJson(json: any): Summary {
@@ -214,7 +220,28 @@ export class Summary {
             json.webpageVersion,
             json.uuid,
             json.cpuCores,
-            json.totalMemory
+            json.totalMemory,
+            json.team ?? null
+        );
+    }
+}
+
+export class AuthStatus {
+    required: boolean;
+    authenticated: boolean;
+    publicRead: boolean;
+
+    constructor(required: boolean, authenticated: boolean, publicRead: boolean) {
+        this.required = required;
+        this.authenticated = authenticated;
+        this.publicRead = publicRead;
+    }
+
+    static fromJson(json: any): AuthStatus {
+        return new AuthStatus(
+            json.required,
+            json.authenticated,
+            json.publicRead
         );
     }
 }
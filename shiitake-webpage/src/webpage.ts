@@ -42,6 +42,33 @@ function formatPercent(percent: number): string {
     return `${percent.toFixed(2)}%`;
 }
 
+let AUTH_STATUS = await fetcher.getAuthStatus();
+
+let loginForm = document.getElementById("login-form") as HTMLFormElement;
+let loginPassword = document.getElementById("login-password") as HTMLInputElement;
+let logoutButton = document.getElementById("logout-button") as HTMLButtonElement;
+
+export async function updateAuth() {
+    AUTH_STATUS = await fetcher.getAuthStatus();
+    loginForm.hidden = !AUTH_STATUS.required || AUTH_STATUS.authenticated;
+    logoutButton.hidden = !AUTH_STATUS.required || !AUTH_STATUS.authenticated;
+}
+
+loginForm.addEventListener("submit", async (event) => {
+    event.preventDefault();
+    let ok = await fetcher.login(loginPassword.value);
+    loginPassword.value = "";
+    loginPassword.setAttribute("aria-invalid", ok ? "false" : "true");
+    await updateAuth();
+});
+
+logoutButton.addEventListener("click", async () => {
+    await fetcher.logout();
+    await updateAuth();
+});
+
+await updateAuth();
+
 let SUMMARY = await fetcher.getSummary();
 
 class ResourceManager {
@@ -324,6 +351,10 @@ export async function updateSummary() {
     let totalMemory = summaryElement.querySelector("#total-memory")! as HTMLElement;
     totalMemory.innerText = "Total Memory: " + formatBytes(SUMMARY.totalMemory);
 
+    //find a summary child with id "team"
+    let team = summaryElement.querySelector("#team")! as HTMLElement;
+    team.innerText = "Team: " + (SUMMARY.team ?? "Unknown");
+
 
     let metadataElement = document.getElementById("metadata");
     if (!metadataElement) {